@@ -0,0 +1,6 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS polling(
+    id INTEGER PRIMARY KEY NOT NULL,
+    last_update_id INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS drafts(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    category TEXT NOT NULL,
+    reference TEXT NOT NULL DEFAULT "",
+    content TEXT NOT NULL,
+    username TEXT NOT NULL DEFAULT "",
+    nickname TEXT NOT NULL DEFAULT "",
+    user_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    message_thread_id INTEGER,
+    message_id INTEGER NOT NULL,
+    bot_message_id INTEGER,
+    created_at DATETIME NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS feedback_transitions;
+ALTER TABLE feedback ADD COLUMN applied INTEGER NOT NULL DEFAULT 0;
+UPDATE feedback SET applied = 1 WHERE status = 'done';
+ALTER TABLE feedback DROP COLUMN status;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN status TEXT NOT NULL DEFAULT 'new';
+UPDATE feedback SET status = 'done' WHERE applied = 1;
+ALTER TABLE feedback DROP COLUMN applied;
+CREATE TABLE IF NOT EXISTS feedback_transitions(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    feedback_id INTEGER NOT NULL REFERENCES feedback(id) ON DELETE CASCADE,
+    from_status TEXT NOT NULL,
+    to_status TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
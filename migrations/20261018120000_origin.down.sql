@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE feedback DROP COLUMN message_thread_id;
+ALTER TABLE feedback DROP COLUMN message_id;
+ALTER TABLE feedback DROP COLUMN user_id;
+ALTER TABLE feedback DROP COLUMN chat_id;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN chat_id INTEGER;
+ALTER TABLE feedback ADD COLUMN user_id INTEGER;
+ALTER TABLE feedback ADD COLUMN message_id INTEGER;
+ALTER TABLE feedback ADD COLUMN message_thread_id INTEGER;
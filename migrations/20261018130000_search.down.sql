@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS feedback_fts_update;
+DROP TRIGGER IF EXISTS feedback_fts_delete;
+DROP TRIGGER IF EXISTS feedback_fts_insert;
+DROP TABLE IF EXISTS feedback_fts;
@@ -0,0 +1,30 @@
+-- Add up migration script here
+CREATE VIRTUAL TABLE IF NOT EXISTS feedback_fts USING fts5(
+    category,
+    reference,
+    content,
+    username,
+    nickname,
+    content='feedback',
+    content_rowid='id',
+    tokenize='unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS feedback_fts_insert AFTER INSERT ON feedback BEGIN
+    INSERT INTO feedback_fts(rowid, category, reference, content, username, nickname)
+    VALUES (new.id, new.category, new.reference, new.content, new.username, new.nickname);
+END;
+
+CREATE TRIGGER IF NOT EXISTS feedback_fts_delete AFTER DELETE ON feedback BEGIN
+    INSERT INTO feedback_fts(feedback_fts, rowid, category, reference, content, username, nickname)
+    VALUES ('delete', old.id, old.category, old.reference, old.content, old.username, old.nickname);
+END;
+
+CREATE TRIGGER IF NOT EXISTS feedback_fts_update AFTER UPDATE ON feedback BEGIN
+    INSERT INTO feedback_fts(feedback_fts, rowid, category, reference, content, username, nickname)
+    VALUES ('delete', old.id, old.category, old.reference, old.content, old.username, old.nickname);
+    INSERT INTO feedback_fts(rowid, category, reference, content, username, nickname)
+    VALUES (new.id, new.category, new.reference, new.content, new.username, new.nickname);
+END;
+
+INSERT INTO feedback_fts(feedback_fts) VALUES ('rebuild');
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DELETE FROM feedback WHERE deleted_at IS NOT NULL;
+ALTER TABLE feedback DROP COLUMN deleted_at;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN deleted_at DATETIME;
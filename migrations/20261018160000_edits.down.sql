@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS feedback_origin_idx;
+DROP TABLE IF EXISTS feedback_edits;
+ALTER TABLE feedback DROP COLUMN mattermost_post_id;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN mattermost_post_id TEXT;
+CREATE TABLE IF NOT EXISTS feedback_edits(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    feedback_id INTEGER NOT NULL REFERENCES feedback(id) ON DELETE CASCADE,
+    reference TEXT NOT NULL,
+    content TEXT NOT NULL,
+    edited_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS feedback_origin_idx ON feedback(chat_id, message_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE drafts DROP COLUMN attachment;
+DROP INDEX IF EXISTS attachments_feedback_idx;
+DROP TABLE IF EXISTS attachments;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS feedback_bot_message_idx;
+ALTER TABLE feedback DROP COLUMN parent_id;
+ALTER TABLE feedback DROP COLUMN bot_message_id;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN bot_message_id INTEGER;
+ALTER TABLE feedback ADD COLUMN parent_id INTEGER REFERENCES feedback(id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS feedback_bot_message_idx ON feedback(chat_id, bot_message_id);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE feedback DROP COLUMN duplicate_of;
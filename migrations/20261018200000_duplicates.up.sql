@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE feedback ADD COLUMN duplicate_of INTEGER REFERENCES feedback(id) ON DELETE SET NULL;
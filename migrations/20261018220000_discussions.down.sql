@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS channel_posts_discussion_idx;
+ALTER TABLE channel_posts DROP COLUMN discussion_message_id;
+ALTER TABLE channel_posts DROP COLUMN discussion_chat_id;
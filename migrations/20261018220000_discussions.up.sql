@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE channel_posts ADD COLUMN discussion_chat_id INTEGER;
+ALTER TABLE channel_posts ADD COLUMN discussion_message_id INTEGER;
+CREATE INDEX IF NOT EXISTS channel_posts_discussion_idx ON channel_posts(discussion_chat_id, discussion_message_id);
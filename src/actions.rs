@@ -0,0 +1,273 @@
+use actix_web::web;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use std::str::FromStr;
+
+use crate::{
+    auth::constant_time_eq,
+    bot::notify_author,
+    channel::ChannelPost,
+    feedback::{Feedback, FeedbackQuery, Status, SortField, Direction},
+    mattermost::Mattermost,
+    telegram::Telegram,
+};
+
+const CANDIDATES: i64 = 20;
+const EPISODES: i64 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Actions{
+    url: String,
+    secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActionContext{
+    pub action: String,
+    pub feedback_id: i64,
+    pub token: String,
+    pub status: Option<String>,
+    pub selected_option: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActionCallback{
+    #[serde(default)]
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub selected_option: Option<String>,
+    pub context: ActionContext,
+}
+
+fn can_schedule(status: Status) -> bool{
+    status == Status::New || status.can_transition_to(Status::Planned)
+}
+
+impl Actions{
+    pub fn new(url: &str, secret: &str) -> Actions{
+        Self{
+            url: url.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+
+    fn token(&self, feedback_id: i64) -> String{
+        Sha256::digest(format!("{}:{}", self.secret, feedback_id).as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn verify(&self, context: &ActionContext) -> bool{
+        constant_time_eq(self.token(context.feedback_id).as_bytes(), context.token.as_bytes())
+    }
+
+    fn context(&self, feedback: &Feedback, action: &str) -> Value{
+        json!({
+            "action": action,
+            "feedback_id": feedback.id,
+            "token": self.token(feedback.id),
+        })
+    }
+
+    fn button(&self, feedback: &Feedback, id: &str, name: &str, to: Status) -> Value{
+        let mut context = self.context(feedback, "status");
+        context["status"] = json!(to.as_str());
+        json!({
+            "id": id,
+            "name": name,
+            "type": "button",
+            "integration": {"url": &self.url, "context": context},
+        })
+    }
+
+    fn select(&self, feedback: &Feedback, id: &str, name: &str, options: Vec<Value>) -> Value{
+        json!({
+            "id": id,
+            "name": name,
+            "type": "select",
+            "options": options,
+            "integration": {"url": &self.url, "context": self.context(feedback, id)},
+        })
+    }
+
+    pub fn props(&self, feedback: &Feedback, actor: Option<&str>, episodes: &[i64],
+            candidates: &[Feedback]) -> Value{
+        let mut text = format!("Estado: **{}**", feedback.status);
+        if let Some(actor) = actor{
+            text.push_str(&format!(" · por @{}", actor));
+        }
+        if let Some(original) = feedback.duplicate_of{
+            text.push_str(&format!(" · duplicado de {}", original));
+        }
+        if feedback.status == Status::Planned && !feedback.reference.is_empty(){
+            text.push_str(&format!(" · episodio {}", feedback.reference));
+        }
+        let mut actions = Vec::new();
+        if feedback.status.can_transition_to(Status::Answered){
+            actions.push(self.button(feedback, "answered", "✅ Respondido", Status::Answered));
+        }
+        if feedback.status.can_transition_to(Status::Rejected){
+            actions.push(self.button(feedback, "rejected", "🚫 Rechazar", Status::Rejected));
+        }
+        let options: Vec<Value> = candidates.iter()
+            .filter(|candidate| candidate.id != feedback.id)
+            .map(|candidate| {
+                let content: String = candidate.content.chars().take(40).collect();
+                json!({"text": format!("{} · {}", candidate.id, content),
+                       "value": candidate.id.to_string()})
+            })
+            .collect();
+        if feedback.status.can_transition_to(Status::Duplicate) && !options.is_empty(){
+            actions.push(self.select(feedback, "duplicate", "Duplicado de…", options));
+        }
+        if can_schedule(feedback.status){
+            if episodes.is_empty(){
+                actions.push(self.button(feedback, "planned", "🗓 Planificar", Status::Planned));
+            }else{
+                let options = episodes.iter()
+                    .map(|episode| json!({"text": format!("Episodio {}", episode),
+                                          "value": episode.to_string()}))
+                    .collect();
+                actions.push(self.select(feedback, "schedule", "Programar para episodio", options));
+            }
+        }
+        json!({
+            "attachments": [{
+                "text": text,
+                "actions": actions,
+            }],
+        })
+    }
+
+    pub async fn props_for(&self, pool: &web::Data<SqlitePool>, feedback: &Feedback,
+            actor: Option<&str>) -> Value{
+        let episodes = match ChannelPost::latest_episode(pool).await{
+            Ok(Some(latest)) => (latest + 1 ..= latest + EPISODES).collect(),
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                println!("No he podido leer el último episodio: {}", e);
+                Vec::new()
+            },
+        };
+        let query = FeedbackQuery{
+            category: Some(feedback.category.clone()),
+            sort: Some(SortField::CreatedAt),
+            direction: Some(Direction::Desc),
+            limit: Some(CANDIDATES),
+            ..Default::default()
+        };
+        let candidates = match query.fetch(pool, None).await{
+            Ok(page) => page.items,
+            Err(e) => {
+                println!("No he podido leer los candidatos a duplicado: {}", e);
+                Vec::new()
+            },
+        };
+        self.props(feedback, actor, &episodes, &candidates)
+    }
+
+    pub async fn handle(&self, pool: &web::Data<SqlitePool>, telegram: &Telegram,
+            mattermost: &Mattermost, callback: &ActionCallback) -> Value{
+        let context = &callback.context;
+        let feedback = match Feedback::read(pool, context.feedback_id).await{
+            Ok(feedback) => feedback,
+            Err(_) => return json!({"ephemeral_text":
+                format!("No existe el feedback {}", context.feedback_id)}),
+        };
+        let actor = match &callback.user_name{
+            Some(user_name) => user_name.clone(),
+            None => mattermost.get_user(&callback.user_id).await.ok()
+                .and_then(|user| user["username"].as_str().map(|name| name.to_string()))
+                .unwrap_or_else(|| callback.user_id.clone()),
+        };
+        let selected = context.selected_option.as_ref().or(callback.selected_option.as_ref());
+        let result = match context.action.as_str(){
+            "status" => match context.status.as_deref().map(Status::from_str){
+                Some(Ok(Status::Planned)) => feedback.plan(pool, &feedback.reference, &actor).await
+                    .map_err(|e| e.to_string()),
+                Some(Ok(to)) => feedback.transition(pool, to, &actor).await
+                    .map_err(|e| e.to_string()),
+                _ => Err("Estado desconocido".to_string()),
+            },
+            "duplicate" => match selected.and_then(|value| value.parse::<i64>().ok()){
+                Some(original) if original != feedback.id && Feedback::read(pool, original).await.is_ok() => {
+                    feedback.mark_duplicate(pool, original, &actor).await
+                        .map_err(|e| e.to_string())
+                },
+                _ => Err("Elige otro feedback".to_string()),
+            },
+            "schedule" => match selected{
+                Some(episode) if can_schedule(feedback.status) => feedback.plan(pool, episode, &actor)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Some(_) => Err(format!("No se puede planificar desde {}", feedback.status)),
+                None => Err("Elige un episodio".to_string()),
+            },
+            other => Err(format!("Acción desconocida {}", other)),
+        };
+        match result{
+            Ok(feedback) => {
+                if let Err(e) = notify_author(telegram, &feedback).await{
+                    println!("No he podido avisar al autor de {}: {}", feedback.id, e);
+                }
+                json!({
+                    "update": {
+                        "message": &feedback.content,
+                        "props": self.props_for(pool, &feedback, Some(&actor)).await,
+                    },
+                })
+            },
+            Err(e) => json!({"ephemeral_text": e}),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::actions::{Actions, ActionContext};
+    use crate::feedback::{Feedback, FeedbackData, Status};
+    use crate::test_utils::memory_pool;
+
+    #[actix_rt::test]
+    async fn builds_actions_for_the_status() {
+        let pool = memory_pool().await;
+        let actions = Actions::new("https://bot.local/mattermost/action", "secreto");
+        let original = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "Un podcast de Rust",
+            "Lorenzo", "atareao", "Telegram"), None).await.unwrap();
+        let feedback = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "Otro podcast de Rust",
+            "Juan", "juan", "Telegram"), None).await.unwrap();
+
+        let props = actions.props_for(&pool, &feedback, None).await;
+        let attachment = &props["attachments"][0];
+        assert_eq!(attachment["text"], "Estado: **new**");
+        let ids: Vec<&str> = attachment["actions"].as_array().unwrap().iter()
+            .map(|action| action["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["answered", "rejected", "duplicate", "planned"]);
+        let context = &attachment["actions"][0]["integration"]["context"];
+        assert_eq!(context["feedback_id"], feedback.id);
+
+        let mut context: ActionContext = serde_json::from_value(context.clone()).unwrap();
+        assert!(actions.verify(&context));
+        context.feedback_id = original.id;
+        assert!(!actions.verify(&context));
+        assert!(!Actions::new("https://bot.local", "otro").verify(&context));
+
+        let props = actions.props(&feedback, Some("lorenzo"), &[12, 13], std::slice::from_ref(&feedback));
+        let attachment = &props["attachments"][0];
+        assert!(attachment["text"].as_str().unwrap().ends_with("por @lorenzo"));
+        let ids: Vec<&str> = attachment["actions"].as_array().unwrap().iter()
+            .map(|action| action["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["answered", "rejected", "schedule"]);
+        assert_eq!(attachment["actions"][2]["options"][1]["value"], "13");
+
+        let done = feedback.transition(&pool, Status::Answered, "test").await.unwrap()
+            .transition(&pool, Status::Done, "test").await.unwrap();
+        let props = actions.props(&done, None, &[12], &[original]);
+        assert!(props["attachments"][0]["actions"].as_array().unwrap().is_empty());
+    }
+}
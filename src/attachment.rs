@@ -0,0 +1,254 @@
+use actix_web::web;
+use sqlx::{sqlite::SqlitePool, FromRow, Error};
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::{fmt, fs, path::Path};
+use zip::result::ZipError;
+
+use crate::{update::Message, telegram::{Telegram, TelegramError}};
+
+#[derive(Debug)]
+pub enum AttachmentError{
+    Telegram(TelegramError),
+    Io(std::io::Error),
+    Sqlx(Error),
+    Zip(ZipError),
+    Missing(String),
+}
+
+impl fmt::Display for AttachmentError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            AttachmentError::Telegram(e) => write!(f, "{}", e),
+            AttachmentError::Io(e) => write!(f, "{}", e),
+            AttachmentError::Sqlx(e) => write!(f, "{}", e),
+            AttachmentError::Zip(e) => write!(f, "{}", e),
+            AttachmentError::Missing(file_id) => write!(f, "Telegram has no path for {}", file_id),
+        }
+    }
+}
+
+impl From<TelegramError> for AttachmentError{
+    fn from(e: TelegramError) -> Self{
+        AttachmentError::Telegram(e)
+    }
+}
+
+impl From<std::io::Error> for AttachmentError{
+    fn from(e: std::io::Error) -> Self{
+        AttachmentError::Io(e)
+    }
+}
+
+impl From<ZipError> for AttachmentError{
+    fn from(e: ZipError) -> Self{
+        AttachmentError::Zip(e)
+    }
+}
+
+impl From<Error> for AttachmentError{
+    fn from(e: Error) -> Self{
+        AttachmentError::Sqlx(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRef{
+    pub kind: String,
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<i64>,
+    pub duration: Option<i64>,
+}
+
+impl FileRef{
+    pub fn from_message(message: &Message) -> Option<FileRef>{
+        if let Some(photo) = message.photo.iter().max_by_key(|photo| photo.width * photo.height){
+            return Some(FileRef{
+                kind: "photo".to_string(),
+                file_id: photo.file_id.clone(),
+                file_unique_id: photo.file_unique_id.clone(),
+                file_name: None,
+                mime_type: Some("image/jpeg".to_string()),
+                file_size: photo.file_size,
+                duration: None,
+            });
+        }
+        [("voice", &message.voice), ("audio", &message.audio),
+         ("video", &message.video), ("document", &message.document)]
+            .iter()
+            .find_map(|(kind, media)| media.as_ref().map(|media| FileRef{
+                kind: kind.to_string(),
+                file_id: media.file_id.clone(),
+                file_unique_id: media.file_unique_id.clone(),
+                file_name: media.file_name.clone(),
+                mime_type: media.mime_type.clone(),
+                file_size: media.file_size,
+                duration: media.duration,
+            }))
+    }
+
+    pub fn is_audio(&self) -> bool{
+        self.kind == "voice" || self.kind == "audio"
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Attachment{
+    pub id: i64,
+    pub feedback_id: i64,
+    pub kind: String,
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<i64>,
+    pub duration: Option<i64>,
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+const COLUMNS: &str = "id, feedback_id, kind, file_id, file_unique_id, file_name,
+                       mime_type, file_size, duration, path, created_at";
+
+impl Attachment{
+    pub async fn download(pool: &web::Data<SqlitePool>, telegram: &Telegram,
+            directory: &str, feedback_id: i64, file: &FileRef) -> Result<Attachment, AttachmentError>{
+        let info = telegram.get_file(&file.file_id).await?;
+        let file_path = info.file_path
+            .ok_or_else(|| AttachmentError::Missing(file.file_id.clone()))?;
+        let content = telegram.download_file(&file_path).await?;
+        let extension = Path::new(&file_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default();
+        let folder = Path::new(directory).join(feedback_id.to_string());
+        fs::create_dir_all(&folder)?;
+        let path = folder.join(format!("{}{}", file.file_unique_id, extension));
+        fs::write(&path, &content)?;
+        Ok(Attachment::create(pool, feedback_id, file, &path.to_string_lossy(),
+                              content.len() as i64).await?)
+    }
+
+    pub async fn create(pool: &web::Data<SqlitePool>, feedback_id: i64, file: &FileRef,
+            path: &str, size: i64) -> Result<Attachment, Error>{
+        let created_at = Utc::now().naive_utc();
+        let sql = format!("INSERT INTO attachments (feedback_id, kind, file_id,
+                   file_unique_id, file_name, mime_type, file_size, duration, path,
+                   created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                   RETURNING {}", COLUMNS);
+        sqlx::query_as::<_, Attachment>(&sql)
+            .bind(feedback_id)
+            .bind(&file.kind)
+            .bind(&file.file_id)
+            .bind(&file.file_unique_id)
+            .bind(&file.file_name)
+            .bind(&file.mime_type)
+            .bind(file.file_size.unwrap_or(size))
+            .bind(file.duration)
+            .bind(path)
+            .bind(created_at)
+            .fetch_one(pool.get_ref())
+            .await
+    }
+
+    pub async fn read(pool: &web::Data<SqlitePool>, feedback_id: i64,
+            id: i64) -> Result<Option<Attachment>, Error>{
+        let sql = format!("SELECT {} FROM attachments WHERE feedback_id = $1 AND id = $2
+                           AND feedback_id IN (SELECT id FROM feedback WHERE deleted_at IS NULL)",
+                          COLUMNS);
+        sqlx::query_as::<_, Attachment>(&sql)
+            .bind(feedback_id)
+            .bind(id)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_all(pool: &web::Data<SqlitePool>, feedback_id: i64) -> Result<Vec<Attachment>, Error>{
+        let sql = format!("SELECT {} FROM attachments WHERE feedback_id = $1 ORDER BY id",
+                          COLUMNS);
+        sqlx::query_as::<_, Attachment>(&sql)
+            .bind(feedback_id)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub fn name(&self) -> String{
+        match &self.file_name{
+            Some(file_name) => file_name.clone(),
+            None => Path::new(&self.path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.file_unique_id.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::attachment::{Attachment, FileRef};
+    use crate::feedback::{Feedback, FeedbackData};
+    use crate::update::Message;
+    use serde_json::json;
+    use crate::test_utils::memory_pool;
+
+    fn message(extra: serde_json::Value) -> Message{
+        let mut base = json!({
+            "message_id": 1, "date": 0,
+            "chat": {"id": 1, "type": "private"},
+            "caption": "#idea",
+        });
+        for (key, item) in extra.as_object().unwrap(){
+            base[key] = item.clone();
+        }
+        serde_json::from_value(base).unwrap()
+    }
+
+    #[test]
+    fn picks_the_file() {
+        let photo = message(json!({"photo": [
+            {"file_id": "small", "file_unique_id": "s", "width": 90, "height": 90},
+            {"file_id": "big", "file_unique_id": "b", "width": 800, "height": 600},
+        ]}));
+        let file = FileRef::from_message(&photo).unwrap();
+        assert_eq!(file.kind, "photo");
+        assert_eq!(file.file_id, "big");
+
+        let voice = message(json!({"voice": {"file_id": "v", "file_unique_id": "vu",
+            "duration": 12, "mime_type": "audio/ogg"}}));
+        let file = FileRef::from_message(&voice).unwrap();
+        assert_eq!(file.kind, "voice");
+        assert_eq!(file.duration, Some(12));
+        assert!(file.is_audio());
+        assert!(FileRef::from_message(&message(json!({}))).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn stores_metadata() {
+        let pool = memory_pool().await;
+        let feedback = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "foto",
+            "Lorenzo", "atareao", "Telegram"), None).await.unwrap();
+        let file = FileRef{
+            kind: "document".to_string(),
+            file_id: "doc".to_string(),
+            file_unique_id: "docu".to_string(),
+            file_name: Some("notas.pdf".to_string()),
+            mime_type: Some("application/pdf".to_string()),
+            file_size: None,
+            duration: None,
+        };
+        let attachment = Attachment::create(&pool, feedback.id, &file, "attachments/1/docu.pdf", 42)
+            .await.unwrap();
+        assert_eq!(attachment.file_size, Some(42));
+        assert_eq!(attachment.name(), "notas.pdf");
+        let all = Attachment::read_all(&pool, feedback.id).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert!(Attachment::read(&pool, feedback.id + 1, attachment.id).await.unwrap().is_none());
+        assert!(Attachment::read(&pool, feedback.id, attachment.id).await.unwrap().is_some());
+        feedback.delete(&pool).await.unwrap();
+        assert!(Attachment::read(&pool, feedback.id, attachment.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use actix_web::{web, dev::Payload, error::InternalError, FromRequest, HttpRequest,
+                HttpResponse, http::header::AUTHORIZATION};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row, query, Error};
+use serde::Serialize;
+use serde_json::json;
+use chrono::{DateTime, Duration, Utc};
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use std::{fmt, future::Future, pin::Pin, str::FromStr};
+
+const TOKEN_PREFIX: &str = "stb";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Scope{
+    #[serde(rename = "feedback:read")]
+    FeedbackRead,
+    #[serde(rename = "feedback:write")]
+    FeedbackWrite,
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Scope::FeedbackRead => "feedback:read",
+            Scope::FeedbackWrite => "feedback:write",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scope{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s{
+            "feedback:read" => Ok(Scope::FeedbackRead),
+            "feedback:write" => Ok(Scope::FeedbackWrite),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!("Unknown scope {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey{
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip)]
+    key_hash: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+const COLUMNS: &str = "id, name, prefix, key_hash, scopes, expires_at,
+                       last_used_at, revoked_at, created_at";
+
+fn from_row(row: SqliteRow) -> ApiKey{
+    let scopes: String = row.get("scopes");
+    ApiKey{
+        id: row.get("id"),
+        name: row.get("name"),
+        prefix: row.get("prefix"),
+        key_hash: row.get("key_hash"),
+        scopes: scopes.split_whitespace()
+            .filter_map(|scope| Scope::from_str(scope).ok())
+            .collect(),
+        expires_at: row.get("expires_at"),
+        last_used_at: row.get("last_used_at"),
+        revoked_at: row.get("revoked_at"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn random_string(length: usize) -> String{
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+fn hash(secret: &str) -> String{
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool{
+    if a.len() != b.len(){
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl ApiKey{
+    pub async fn mint(pool: &web::Data<SqlitePool>, name: &str, scopes: &[Scope],
+            expires_in: Option<Duration>) -> Result<(ApiKey, String), Error>{
+        let prefix = random_string(8);
+        let secret = random_string(40);
+        let created_at = Utc::now();
+        let expires_at = expires_in.map(|duration| (created_at + duration).naive_utc());
+        let scopes = scopes.iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let sql = format!("INSERT INTO api_keys (name, prefix, key_hash, scopes,
+                           expires_at, created_at) VALUES ($1, $2, $3, $4, $5, $6)
+                           RETURNING {}", COLUMNS);
+        let api_key = query(&sql)
+            .bind(name)
+            .bind(&prefix)
+            .bind(hash(&secret))
+            .bind(scopes)
+            .bind(expires_at)
+            .bind(created_at.naive_utc())
+            .map(from_row)
+            .fetch_one(pool.get_ref())
+            .await?;
+        Ok((api_key, format!("{}_{}_{}", TOKEN_PREFIX, prefix, secret)))
+    }
+
+    pub async fn revoke(pool: &web::Data<SqlitePool>, prefix: &str) -> Result<bool, Error>{
+        let revoked_at = Utc::now().naive_utc();
+        let result = query("UPDATE api_keys SET revoked_at = $1
+                            WHERE prefix = $2 AND revoked_at IS NULL")
+            .bind(revoked_at)
+            .bind(prefix)
+            .execute(pool.get_ref())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn read_all(pool: &web::Data<SqlitePool>) -> Result<Vec<ApiKey>, Error>{
+        let sql = format!("SELECT {} FROM api_keys ORDER BY id", COLUMNS);
+        query(&sql)
+            .map(from_row)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub async fn verify(pool: &web::Data<SqlitePool>, token: &str) -> Result<Option<ApiKey>, Error>{
+        let mut parts = token.splitn(3, '_');
+        let (prefix, secret) = match (parts.next(), parts.next(), parts.next()){
+            (Some(TOKEN_PREFIX), Some(prefix), Some(secret)) => (prefix, secret),
+            _ => return Ok(None),
+        };
+        let sql = format!("SELECT {} FROM api_keys WHERE prefix = $1", COLUMNS);
+        let api_key = match query(&sql)
+                .bind(prefix)
+                .map(from_row)
+                .fetch_optional(pool.get_ref())
+                .await?{
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+        if !constant_time_eq(hash(secret).as_bytes(), api_key.key_hash.as_bytes())
+                || !api_key.is_active(){
+            return Ok(None);
+        }
+        query("UPDATE api_keys SET last_used_at = $1 WHERE id = $2")
+            .bind(Utc::now().naive_utc())
+            .bind(api_key.id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok(Some(api_key))
+    }
+
+    pub fn is_active(&self) -> bool{
+        self.revoked_at.is_none() &&
+            self.expires_at.map(|expires_at| expires_at > Utc::now()).unwrap_or(true)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool{
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), actix_web::Error>{
+        if self.allows(scope){
+            Ok(())
+        }else{
+            Err(unauthorized(403, &format!("Forbidden, {} scope is required", scope)))
+        }
+    }
+}
+
+fn unauthorized(code: u16, description: &str) -> actix_web::Error{
+    let body = json!({
+        "code": code,
+        "status": "KO",
+        "content": {"description": description},
+    });
+    let response = if code == 401{
+        HttpResponse::Unauthorized().json(body)
+    }else{
+        HttpResponse::Forbidden().json(body)
+    };
+    InternalError::from_response(description.to_string(), response).into()
+}
+
+impl FromRequest for ApiKey{
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future{
+        let pool = req.app_data::<web::Data<SqlitePool>>().cloned();
+        let token = req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|value| value.trim().to_string());
+        Box::pin(async move{
+            let (pool, token) = match (pool, token){
+                (Some(pool), Some(token)) => (pool, token),
+                _ => return Err(unauthorized(401, "Unauthorized")),
+            };
+            match ApiKey::verify(&pool, &token).await{
+                Ok(Some(api_key)) => Ok(api_key),
+                Ok(None) => Err(unauthorized(401, "Unauthorized")),
+                Err(e) => {
+                    println!("No he podido verificar la clave: {}", e);
+                    Err(unauthorized(401, "Unauthorized"))
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::auth::{ApiKey, Scope, constant_time_eq};
+    use chrono::Duration;
+    use crate::test_utils::memory_pool;
+
+    #[test]
+    fn compares_in_constant_time() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+
+    #[actix_rt::test]
+    async fn mint_verify_and_revoke() {
+        let pool = memory_pool().await;
+
+        let (api_key, token) = ApiKey::mint(&pool, "podcast", &[Scope::FeedbackRead], None)
+            .await.unwrap();
+        let verified = ApiKey::verify(&pool, &token).await.unwrap().unwrap();
+        assert_eq!(verified.id, api_key.id);
+        assert!(verified.allows(Scope::FeedbackRead));
+        assert!(!verified.allows(Scope::FeedbackWrite));
+        assert!(ApiKey::verify(&pool, &format!("{}x", token)).await.unwrap().is_none());
+        assert!(ApiKey::verify(&pool, "Bearer nada").await.unwrap().is_none());
+        assert!(ApiKey::read_all(&pool).await.unwrap()[0].last_used_at.is_some());
+
+        assert!(ApiKey::revoke(&pool, &api_key.prefix).await.unwrap());
+        assert!(ApiKey::verify(&pool, &token).await.unwrap().is_none());
+
+        let (_, token) = ApiKey::mint(&pool, "old", &[Scope::Admin], Some(Duration::seconds(-1)))
+            .await.unwrap();
+        assert!(ApiKey::verify(&pool, &token).await.unwrap().is_none());
+    }
+}
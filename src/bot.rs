@@ -0,0 +1,767 @@
+use actix_web::{web, rt::time::sleep};
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::time::Duration;
+
+use crate::{
+    feedback::{Feedback, FeedbackData, Origin, Status},
+    channel::ChannelPost,
+    attachment::{Attachment, FileRef},
+    draft::Draft,
+    message::{
+        get_user,
+        hashtags,
+        command,
+        is_mention,
+        permalink,
+    },
+    update::{Update, Message, CallbackQuery, InlineKeyboardMarkup, InlineKeyboardButton,
+             MessageReactionUpdated},
+    vote::{self, Vote},
+    telegram::{Telegram, SendMessage, ParseMode, TelegramError},
+    mattermost::Mattermost,
+    config::{Categories, MENTION},
+    BotContext,
+};
+
+const TOP: i64 = 10;
+
+pub async fn process_update(context: &BotContext, update: &Update){
+    let BotContext{pool, categories, bot_info, telegram, mattermost, ..} = context;
+    if let Some(message) = &update.message{
+        link_discussion(pool, message).await;
+        if message.is_automatic_forward{
+            return;
+        }
+        let (name, nick) = get_user(message);
+        let user = if !nick.is_empty() {format!("@{}", nick)} else {name.clone()};
+        let is_help = command("ayuda", message, &bot_info.user);
+        let is_top = command("top", message, &bot_info.user);
+        let is_command = is_help || is_top;
+        if is_help{
+            send(telegram, answer(message, &help_text(categories))
+                .parse_mode(ParseMode::Markdown)).await;
+        };
+        if is_top{
+            send(telegram, answer(message, &top_text(pool, categories, message).await)).await;
+        }
+        let tagged = hashtags(message, &hashtag_keys(categories));
+        for category in categories.iter(){
+            let hashtag = match category.hashtags().find_map(|hashtag| tagged.find(hashtag)){
+                Some(hashtag) => hashtag,
+                None => continue,
+            };
+            let reference = match (&hashtag.reference, category.reference){
+                (Some(reference), _) => reference.clone(),
+                (None, true) => ChannelPost::episode_for(pool, message,
+                    &bot_info.episode_pattern).await.unwrap_or_default(),
+                (None, false) => String::new(),
+            };
+            let attachment = FileRef::from_message(message).or_else(|| message.reply_to_message
+                .as_ref()
+                .filter(|_| category.voice)
+                .and_then(|reply| FileRef::from_message(reply))
+                .filter(|file| file.is_audio()));
+            let content = match attachment.as_ref(){
+                Some(file) if tagged.content.is_empty() && category.voice && file.is_audio() =>
+                    voice_text(file),
+                _ => tagged.content.clone(),
+            };
+            if content.is_empty(){
+                send(telegram, answer(message, &category.empty_text(&user))).await;
+                continue;
+            }
+            match Draft::new_from(pool, &category.name, &reference, &content, message,
+                                  attachment.as_ref()).await{
+                Ok(mut draft) => ask_confirmation(pool, telegram, message, &mut draft).await,
+                Err(_) => send(telegram, answer(message, &category.error_text(&user))).await,
+            }
+        }
+        if tagged.hashtags.is_empty() && !is_command{
+            if let Some(root) = follow_up_root(pool, message).await{
+                post_follow_up(context, message, &root, &tagged.content).await;
+            }else if is_mention(message, &bot_info.user){
+                forward_mention(context, message, &name, &nick, &user).await;
+            }
+        }
+    }else if let Some(post) = &update.channel_post{
+        process_channel_post(context, post).await;
+    }else if let Some(post) = &update.edited_channel_post{
+        if let Err(e) = ChannelPost::save(pool, post, &bot_info.episode_pattern).await{
+            println!("No he podido guardar la publicación del canal: {}", e);
+        }
+    }else if let Some(message) = &update.edited_message{
+        process_edit(pool, categories, mattermost, message).await;
+    }else if let Some(reaction) = &update.message_reaction{
+        process_reaction(pool, &bot_info.upvote_emoji, reaction).await;
+    }else if let Some(callback_query) = &update.callback_query{
+        process_callback_query(context, callback_query).await;
+    }else{
+        println!("Update {} ignorado", update.update_id);
+    }
+}
+
+async fn process_channel_post(context: &BotContext, post: &Message){
+    let BotContext{pool, categories, bot_info, zinc, ..} = context;
+    let saved = match ChannelPost::save(pool, post, &bot_info.episode_pattern).await{
+        Ok(saved) => saved,
+        Err(e) => {
+            println!("No he podido guardar la publicación del canal: {}", e);
+            return;
+        },
+    };
+    let tagged = hashtags(post, &hashtag_keys(categories));
+    if tagged.content.is_empty(){
+        return;
+    }
+    let name = post.chat.title.clone().unwrap_or_default();
+    let nick = post.chat.username.clone().unwrap_or_default();
+    for category in categories.iter(){
+        let hashtag = match category.hashtags().find_map(|hashtag| tagged.find(hashtag)){
+            Some(hashtag) => hashtag,
+            None => continue,
+        };
+        let reference = match (&hashtag.reference, category.reference){
+            (Some(reference), _) => reference.clone(),
+            (None, true) => saved.episode.clone().unwrap_or_default(),
+            (None, false) => String::new(),
+        };
+        match Feedback::new_from(pool, &FeedbackData::new(&category.name, &reference, &tagged.content,
+                &name, &nick, "Telegram"), Some(&Origin::from_message(post))).await{
+            Ok(mut feedback) => {
+                let attachments = save_attachment(context, &feedback,
+                    FileRef::from_message(post).as_ref()).await;
+                publish_post(context, &mut feedback, &category.channel_id,
+                             &tagged.content, &attachments, None).await;
+                if let Err(e) = zinc.publish(&json!([{
+                    "src": "Telegram",
+                    "type": category.zinc_type(),
+                    "from": &name,
+                    "message": &tagged.content,
+                }])).await{
+                    println!("No he podido publicar en zinc: {:?}", e);
+                }
+            },
+            Err(e) => println!("No he podido registrar la publicación del canal: {}", e),
+        }
+    }
+}
+
+async fn link_discussion(pool: &web::Data<SqlitePool>, message: &Message){
+    let forwards = std::iter::once(message).chain(message.reply_to_message.as_deref());
+    for forward in forwards.filter(|forward| forward.is_automatic_forward){
+        if let Err(e) = ChannelPost::link_discussion(pool, forward).await{
+            println!("No he podido enlazar la publicación del canal: {}", e);
+        }
+    }
+}
+
+fn hashtag_keys(categories: &Categories) -> Vec<(&str, bool)>{
+    categories.iter()
+        .flat_map(|category| category.hashtags()
+            .map(move |hashtag| (hashtag, category.reference)))
+        .collect()
+}
+
+async fn process_edit(pool: &web::Data<SqlitePool>, categories: &Categories,
+        mattermost: &Mattermost, message: &Message){
+    let tagged = hashtags(message, &hashtag_keys(categories));
+    match Draft::read_by_origin(pool, message.chat.id, message.message_id).await{
+        Ok(drafts) if !drafts.is_empty() => {
+            for mut draft in drafts{
+                let reference = categories.get(&draft.category)
+                    .and_then(|category| category.hashtags().find_map(|hashtag| tagged.find(hashtag)))
+                    .and_then(|hashtag| hashtag.reference.clone())
+                    .unwrap_or_else(|| draft.reference.clone());
+                if !tagged.content.is_empty(){
+                    if let Err(e) = draft.set_content(pool, &reference, &tagged.content).await{
+                        println!("No he podido actualizar el borrador {}: {}", draft.id, e);
+                    }
+                }
+            }
+            return;
+        },
+        Ok(_) => {},
+        Err(e) => println!("No he podido leer el borrador: {}", e),
+    }
+    let feedbacks = match Feedback::read_by_origin(pool, message.chat.id, message.message_id).await{
+        Ok(feedbacks) => feedbacks,
+        Err(e) => {
+            println!("No he podido leer el feedback editado: {}", e);
+            return;
+        },
+    };
+    for feedback in feedbacks{
+        let (reference, content, post) = if feedback.category == MENTION{
+            let content = message.text.clone()
+                .or_else(|| message.caption.clone())
+                .unwrap_or_default();
+            let user = display_user(&feedback.username, &feedback.nickname);
+            let post = mention_text(message, &user, &content);
+            (feedback.reference.clone(), content, post)
+        }else{
+            let reference = categories.get(&feedback.category)
+                .and_then(|category| category.hashtags().find_map(|hashtag| tagged.find(hashtag)))
+                .and_then(|hashtag| hashtag.reference.clone())
+                .unwrap_or_else(|| feedback.reference.clone());
+            (reference, tagged.content.clone(), tagged.content.clone())
+        };
+        if content.is_empty() || (content == feedback.content && reference == feedback.reference){
+            continue;
+        }
+        match feedback.edit(pool, &reference, &content).await{
+            Ok(feedback) => {
+                if let Some(post_id) = &feedback.mattermost_post_id{
+                    if let Err(e) = mattermost.update_post(post_id, &post).await{
+                        println!("No he podido editar el mensaje de Mattermost: {}", e);
+                    }
+                }
+            },
+            Err(e) => println!("No he podido editar el feedback {}: {}", feedback.id, e),
+        }
+    }
+}
+
+async fn save_attachment(context: &BotContext, feedback: &Feedback,
+        file: Option<&FileRef>) -> Vec<Attachment>{
+    let BotContext{pool, telegram, bot_info, ..} = context;
+    let file = match file{
+        Some(file) => file,
+        None => return Vec::new(),
+    };
+    match Attachment::download(pool, telegram, &bot_info.attachments_dir, feedback.id, file).await{
+        Ok(attachment) => vec![attachment],
+        Err(e) => {
+            println!("No he podido guardar el adjunto de {}: {}", feedback.id, e);
+            Vec::new()
+        },
+    }
+}
+
+async fn follow_up_root(pool: &web::Data<SqlitePool>, message: &Message) -> Option<Feedback>{
+    let reply = message.reply_to_message.as_ref()?;
+    let user_id = message.from.as_ref()?.id;
+    let replied = match Feedback::read_by_reply(pool, message.chat.id, reply.message_id).await{
+        Ok(replied) => replied?,
+        Err(e) => {
+            println!("No he podido leer el feedback respondido: {}", e);
+            return None;
+        },
+    };
+    if replied.user_id != Some(user_id){
+        return None;
+    }
+    match replied.thread_root(pool).await{
+        Ok(root) if root.mattermost_post_id.is_some() => Some(root),
+        Ok(_) => None,
+        Err(e) => {
+            println!("No he podido leer el hilo de {}: {}", replied.id, e);
+            None
+        },
+    }
+}
+
+async fn post_follow_up(context: &BotContext, message: &Message, root: &Feedback, content: &str){
+    let BotContext{pool, categories, bot_info, ..} = context;
+    let (name, nick) = get_user(message);
+    let file = FileRef::from_message(message);
+    if content.is_empty() && file.is_none(){
+        return;
+    }
+    let channel_id = if root.category == MENTION{
+        bot_info.mention_channel_id.clone()
+    }else{
+        categories.get(&root.category).map(|category| category.channel_id.clone())
+    };
+    let channel_id = match channel_id{
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    let text = if root.category == MENTION{
+        mention_text(message, &display_user(&name, &nick), content)
+    }else{
+        content.to_string()
+    };
+    match Feedback::new_from(pool, &FeedbackData::new(&root.category, &root.reference, content,
+            &name, &nick, "Telegram"), Some(&Origin::from_message(message))).await{
+        Ok(mut feedback) => {
+            if let Err(e) = feedback.set_parent(pool, root.id).await{
+                println!("No he podido enlazar el feedback {}: {}", feedback.id, e);
+            }
+            let attachments = save_attachment(context, &feedback, file.as_ref()).await;
+            publish_post(context, &mut feedback, &channel_id, &text, &attachments,
+                         root.mattermost_post_id.as_deref()).await;
+        },
+        Err(e) => println!("No he podido registrar la respuesta a {}: {}", root.id, e),
+    }
+}
+
+async fn publish_post(context: &BotContext, feedback: &mut Feedback, channel_id: &str,
+        text: &str, attachments: &[Attachment], root_id: Option<&str>){
+    let BotContext{pool, bot_info, mattermost, ..} = context;
+    let mut file_ids = Vec::new();
+    for attachment in attachments{
+        let uploaded = match std::fs::read(&attachment.path){
+            Ok(content) => mattermost.upload_file(channel_id, &attachment.name(), content).await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match uploaded{
+            Ok(Some(file_id)) => file_ids.push(file_id),
+            Ok(None) => println!("Mattermost no ha devuelto el fichero {}", attachment.id),
+            Err(e) => println!("No he podido subir el adjunto {}: {}", attachment.id, e),
+        }
+    }
+    let props = match (&bot_info.actions, root_id){
+        (Some(actions), None) if feedback.category != MENTION =>
+            Some(actions.props_for(pool, feedback, None).await),
+        _ => None,
+    };
+    match mattermost.post_message(channel_id, text, root_id, &file_ids, props.as_ref()).await{
+        Ok(post) => {
+            if let Some(post_id) = post.get("id").and_then(|id| id.as_str()){
+                if let Err(e) = feedback.set_post_id(pool, post_id).await{
+                    println!("No he podido guardar el mensaje de Mattermost: {}", e);
+                }
+            }
+        },
+        Err(e) => println!("No he podido publicar en Mattermost: {}", e),
+    }
+}
+
+fn voice_text(file: &FileRef) -> String{
+    let duration = file.duration.unwrap_or_default();
+    format!("Nota de voz ({}:{:02})", duration / 60, duration % 60)
+}
+
+fn mention_text(message: &Message, user: &str, content: &str) -> String{
+    let chat = message.chat.title.clone()
+        .or_else(|| message.chat.username.clone())
+        .unwrap_or_else(|| "privado".to_string());
+    let mut text = format!("**{}** ha mencionado al bot en {}:\n\n> {}",
+                           user, chat, content.replace('\n', "\n> "));
+    if let Some(link) = permalink(message){
+        text.push_str(&format!("\n\n{}", link));
+    }
+    text
+}
+
+async fn process_reaction(pool: &web::Data<SqlitePool>, upvote_emoji: &[String],
+        reaction: &MessageReactionUpdated){
+    let user = match &reaction.user{
+        Some(user) => user,
+        None => return,
+    };
+    let feedback = match Feedback::read_by_bot_message(pool, reaction.chat.id,
+            reaction.message_id).await{
+        Ok(Some(feedback)) if feedback.category != MENTION => feedback,
+        Ok(_) => return,
+        Err(e) => {
+            println!("No he podido leer el feedback de la reacción: {}", e);
+            return;
+        },
+    };
+    let upvote = reaction.new_reaction.iter()
+        .filter_map(|kind| kind.emoji.as_ref())
+        .any(|emoji| upvote_emoji.contains(emoji));
+    let result = if upvote{
+        Vote::cast(pool, feedback.id, vote::TELEGRAM, &user.id.to_string()).await
+    }else{
+        Vote::withdraw(pool, feedback.id, vote::TELEGRAM, &user.id.to_string()).await
+    };
+    if let Err(e) = result{
+        println!("No he podido registrar el voto a {}: {}", feedback.id, e);
+    }
+}
+
+async fn top_text(pool: &web::Data<SqlitePool>, categories: &Categories, message: &Message) -> String{
+    let asked = message.text.as_deref()
+        .and_then(|text| text.split_whitespace().nth(1))
+        .map(|word| word.trim_start_matches('#'))
+        .and_then(|word| categories.iter().find(|category| category.hashtags()
+            .any(|hashtag| hashtag.eq_ignore_ascii_case(word))));
+    let names: Vec<&str> = categories.iter()
+        .filter(|category| category.votes)
+        .filter(|category| asked.map(|asked| asked.name == category.name).unwrap_or(true))
+        .map(|category| category.name.as_str())
+        .collect();
+    let items = match Feedback::top(pool, &names, TOP).await{
+        Ok(items) => items,
+        Err(e) => {
+            println!("No he podido leer lo más votado: {}", e);
+            return "No he podido leer lo más votado".to_string();
+        },
+    };
+    if items.is_empty(){
+        return "Todavía no hay votos".to_string();
+    }
+    let lines: Vec<String> = items.iter().enumerate().map(|(position, feedback)| {
+        let content: String = feedback.content.chars().take(80).collect();
+        let votes = feedback.votes.unwrap_or_default();
+        format!("{}. {} · {} ({} {})", position + 1, feedback.category, content, votes,
+                if votes == 1 {"voto"} else {"votos"})
+    }).collect();
+    format!("Lo más votado:\n\n{}", lines.join("\n"))
+}
+
+pub async fn sync_reactions(pool: web::Data<SqlitePool>, mattermost: Mattermost,
+        upvote_emoji: Vec<String>, interval: u64){
+    loop{
+        match Feedback::read_open_posts(&pool).await{
+            Ok(items) => {
+                for feedback in items{
+                    let post_id = feedback.mattermost_post_id.clone().unwrap_or_default();
+                    let reactions = match mattermost.get_reactions(&post_id).await{
+                        Ok(reactions) => reactions,
+                        Err(e) => {
+                            println!("No he podido leer las reacciones de {}: {}", post_id, e);
+                            continue;
+                        },
+                    };
+                    let mut user_ids: Vec<String> = reactions.iter()
+                        .filter(|reaction| reaction["emoji_name"].as_str()
+                            .map(|name| upvote_emoji.iter().any(|emoji| emoji == name))
+                            .unwrap_or(false))
+                        .filter_map(|reaction| reaction["user_id"].as_str().map(|id| id.to_string()))
+                        .collect();
+                    user_ids.sort();
+                    user_ids.dedup();
+                    if let Err(e) = Vote::sync(&pool, feedback.id, vote::MATTERMOST, &user_ids).await{
+                        println!("No he podido sincronizar los votos de {}: {}", feedback.id, e);
+                    }
+                }
+            },
+            Err(e) => println!("No he podido leer el feedback abierto: {}", e),
+        }
+        sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+fn help_text(categories: &Categories) -> String{
+    let items: Vec<String> = categories.iter()
+        .map(|category| format!("• {}", category.help_text()))
+        .collect();
+    let hashtags: Vec<String> = categories.iter()
+        .map(|category| format!("`#{}`", category.hashtag()))
+        .collect();
+    format!("Ayuda:
+¿Como colaborar con tus ideas, preguntas y comentarios?
+
+Utilizando `hastags` (#),
+
+{}
+
+Indicarte que {} no tienen que ir necesariamenta al principio o al final del mensaje, pueden ir donde tu quieras.
+
+Reacciona a mis respuestas para votar lo que más te guste y usa /top para ver lo más votado.
+", items.join("\n\n"), hashtags.join(", "))
+}
+
+async fn forward_mention(context: &BotContext, message: &Message, name: &str, nick: &str,
+        user: &str){
+    let BotContext{pool, bot_info, zinc, ..} = context;
+    let content = message.text.clone()
+        .or_else(|| message.caption.clone())
+        .unwrap_or_default();
+    let feedback = Feedback::new_from(pool, &FeedbackData::new(MENTION, "", &content,
+            name, nick, "Telegram"), Some(&Origin::from_message(message))).await;
+    match feedback{
+        Ok(mut feedback) => {
+            let attachments = save_attachment(context, &feedback,
+                FileRef::from_message(message).as_ref()).await;
+            if let Some(channel_id) = &bot_info.mention_channel_id{
+                publish_post(context, &mut feedback, channel_id,
+                             &mention_text(message, user, &content), &attachments, None).await;
+            }
+        },
+        Err(e) => println!("No he podido registrar la mención: {}", e),
+    }
+    if let Err(e) = zinc.publish(&json!([{
+        "src": "Telegram",
+        "type": MENTION,
+        "from": user,
+        "message": &content,
+    }])).await{
+        println!("No he podido publicar en zinc: {:?}", e);
+    }
+}
+
+fn answer(message: &Message, text: &str) -> SendMessage{
+    let thread = if message.is_topic_message {message.message_thread_id} else {None};
+    SendMessage::new(message.chat.id, text)
+        .thread(thread)
+        .reply_to(message.message_id)
+}
+
+async fn send(telegram: &Telegram, message: SendMessage){
+    if let Err(e) = telegram.send_message(&message).await{
+        println!("No he podido enviar el mensaje: {}", e);
+    }
+}
+
+fn display_user(username: &str, nickname: &str) -> String{
+    if !nickname.is_empty() {format!("@{}", nickname)} else {username.to_string()}
+}
+
+fn confirmation_keyboard(draft: &Draft) -> InlineKeyboardMarkup{
+    InlineKeyboardMarkup{
+        inline_keyboard: vec![
+            vec![InlineKeyboardButton::callback("✅ Confirmar", &format!("confirm:{}", draft.id))],
+            vec![InlineKeyboardButton::callback("🔀 Cambiar categoría", &format!("change:{}", draft.id))],
+            vec![InlineKeyboardButton::callback("❌ Cancelar", &format!("cancel:{}", draft.id))],
+        ],
+    }
+}
+
+fn category_keyboard(categories: &Categories, draft: &Draft) -> InlineKeyboardMarkup{
+    let mut inline_keyboard: Vec<Vec<InlineKeyboardButton>> = categories
+        .iter()
+        .map(|category| vec![InlineKeyboardButton::callback(
+            &category.name, &format!("set:{}:{}", draft.id, category.name))])
+        .collect();
+    inline_keyboard.push(vec![InlineKeyboardButton::callback(
+        "❌ Cancelar", &format!("cancel:{}", draft.id))]);
+    InlineKeyboardMarkup{inline_keyboard}
+}
+
+fn confirmation_text(draft: &Draft) -> String{
+    format!("{}, ¿confirmas el envío de tu {}?",
+            display_user(&draft.username, &draft.nickname), draft.category)
+}
+
+async fn ask_confirmation(pool: &web::Data<SqlitePool>, telegram: &Telegram,
+        message: &Message, draft: &mut Draft){
+    let question = answer(message, &confirmation_text(draft))
+        .keyboard(confirmation_keyboard(draft))
+        .silent();
+    match telegram.send_message(&question).await{
+        Ok(sent) => {
+            if let Err(e) = draft.set_bot_message(pool, sent.message_id).await{
+                println!("No he podido guardar el borrador {}: {}", draft.id, e);
+            }
+        },
+        Err(e) => println!("No he podido enviar el mensaje: {}", e),
+    }
+}
+
+async fn process_callback_query(context: &BotContext, callback_query: &CallbackQuery){
+    let BotContext{pool, categories, telegram, zinc, draft_timeout, ..} = context;
+    let data = callback_query.data.clone().unwrap_or_default();
+    let parts: Vec<&str> = data.split(':').collect();
+    let id = match parts.get(1).and_then(|id| id.parse::<i64>().ok()){
+        Some(id) => id,
+        None => {
+            answer_callback(telegram, callback_query, None).await;
+            return;
+        },
+    };
+    let mut draft = match Draft::read(pool, id).await{
+        Ok(Some(draft)) if !draft.is_expired(*draft_timeout) => draft,
+        Ok(None) => {
+            answer_callback(telegram, callback_query, None).await;
+            return;
+        },
+        Ok(Some(_)) => {
+            answer_callback(telegram, callback_query, Some("Este borrador ha caducado")).await;
+            if let Some(message) = &callback_query.message{
+                edit(telegram, message.chat.id, message.message_id,
+                     "Este borrador ha caducado", None).await;
+            }
+            return;
+        },
+        Err(e) => {
+            println!("No he podido leer el borrador {}: {}", id, e);
+            answer_callback(telegram, callback_query, None).await;
+            return;
+        },
+    };
+    if draft.user_id != callback_query.from.id{
+        answer_callback(telegram, callback_query, Some("Solo el autor puede confirmar este mensaje")).await;
+        return;
+    }
+    let user = display_user(&draft.username, &draft.nickname);
+    match parts[0]{
+        "confirm" => {
+            let category = match categories.get(&draft.category){
+                Some(category) => category,
+                None => {
+                    println!("Categoría desconocida: {}", draft.category);
+                    answer_callback(telegram, callback_query, None).await;
+                    return;
+                },
+            };
+            match draft.delete(pool).await{
+                Ok(true) => {},
+                Ok(false) => {
+                    answer_callback(telegram, callback_query, None).await;
+                    return;
+                },
+                Err(e) => {
+                    println!("No he podido borrar el borrador {}: {}", draft.id, e);
+                    answer_callback(telegram, callback_query, None).await;
+                    return;
+                },
+            }
+            let data = FeedbackData::new(&draft.category, &draft.reference, &draft.content,
+                &draft.username, &draft.nickname, "Telegram");
+            match Feedback::new_from(pool, &data, Some(&draft.origin())).await{
+                Ok(mut feedback) => {
+                    edit_prompt(telegram, &draft, &category.thanks_text(&user), None).await;
+                    if let Some(bot_message_id) = draft.bot_message_id{
+                        if let Err(e) = feedback.set_bot_message(pool, bot_message_id).await{
+                            println!("No he podido guardar la respuesta de {}: {}", feedback.id, e);
+                        }
+                    }
+                    let attachments = save_attachment(context, &feedback,
+                        draft.attachment.as_ref()).await;
+                    publish_post(context, &mut feedback, &category.channel_id,
+                                 &draft.content, &attachments, None).await;
+                    if let Err(e) = zinc.publish(&json!([{
+                        "src": "Telegram",
+                        "type": category.zinc_type(),
+                        "from": &user,
+                        "message": &draft.content,
+                    }])).await{
+                        println!("No he podido publicar en zinc: {:?}", e);
+                    }
+                },
+                Err(_) => edit_prompt(telegram, &draft, &category.error_text(&user), None).await,
+            }
+            answer_callback(telegram, callback_query, None).await;
+        },
+        "change" => {
+            edit_prompt(telegram, &draft, "Elige la categoría",
+                        Some(&category_keyboard(categories, &draft))).await;
+            answer_callback(telegram, callback_query, None).await;
+        },
+        "set" => {
+            match parts.get(2).and_then(|name| categories.get(name)){
+                Some(category) => {
+                    if let Err(e) = draft.set_category(pool, &category.name).await{
+                        println!("No he podido actualizar el borrador {}: {}", draft.id, e);
+                    }
+                    edit_prompt(telegram, &draft, &confirmation_text(&draft),
+                                Some(&confirmation_keyboard(&draft))).await;
+                },
+                None => println!("Categoría desconocida: {}", data),
+            }
+            answer_callback(telegram, callback_query, None).await;
+        },
+        "cancel" => {
+            if let Err(e) = draft.delete(pool).await{
+                println!("No he podido borrar el borrador {}: {}", draft.id, e);
+            }
+            edit_prompt(telegram, &draft, "Envío cancelado", None).await;
+            answer_callback(telegram, callback_query, None).await;
+        },
+        _ => answer_callback(telegram, callback_query, None).await,
+    }
+}
+
+fn escape_html(text: &str) -> String{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub async fn notify_author(telegram: &Telegram, feedback: &Feedback) -> Result<(), TelegramError>{
+    let origin = match feedback.origin(){
+        Some(origin) => origin,
+        None => return Ok(()),
+    };
+    let novedad = match feedback.status{
+        Status::Answered => "ya tiene respuesta",
+        Status::Done => "se ha llevado a cabo",
+        _ => return Ok(()),
+    };
+    let user = display_user(&feedback.username, &feedback.nickname);
+    let text = format!("{}, tu {} {} 🎉\n\n<blockquote>{}</blockquote>",
+                       escape_html(&user), feedback.category, novedad,
+                       escape_html(&feedback.content));
+    let message = SendMessage::new(origin.chat_id, &text)
+        .thread(origin.message_thread_id)
+        .reply_to(origin.message_id)
+        .parse_mode(ParseMode::Html);
+    telegram.send_message(&message).await?;
+    Ok(())
+}
+
+pub async fn relay_reply(pool: &web::Data<SqlitePool>, telegram: &Telegram,
+        mattermost: &Mattermost, post_id: &str, user_name: &str, text: &str) -> bool{
+    let post = match mattermost.get_post(post_id).await{
+        Ok(post) => post,
+        Err(e) => {
+            println!("No he podido leer el mensaje de Mattermost {}: {}", post_id, e);
+            return false;
+        },
+    };
+    let root_id = match post["root_id"].as_str(){
+        Some(root_id) if !root_id.is_empty() => root_id,
+        _ => return false,
+    };
+    let feedback = match Feedback::read_by_post_id(pool, root_id).await{
+        Ok(Some(feedback)) => feedback,
+        Ok(None) => return false,
+        Err(e) => {
+            println!("No he podido leer el feedback del hilo {}: {}", root_id, e);
+            return false;
+        },
+    };
+    let origin = match feedback.origin(){
+        Some(origin) => origin,
+        None => return false,
+    };
+    let text = format!("<b>{}</b> ha respondido a tu {}:\n\n{}", escape_html(user_name),
+                       feedback.category, escape_html(text));
+    let message = SendMessage::new(origin.chat_id, &text)
+        .thread(origin.message_thread_id)
+        .reply_to(origin.message_id)
+        .parse_mode(ParseMode::Html);
+    match telegram.send_message(&message).await{
+        Ok(_) => true,
+        Err(e) => {
+            println!("No he podido enviar la respuesta de Mattermost: {}", e);
+            false
+        },
+    }
+}
+
+pub async fn expire_drafts(pool: web::Data<SqlitePool>, telegram: Telegram, draft_timeout: i64){
+    loop{
+        match Draft::read_expired(&pool, draft_timeout).await{
+            Ok(drafts) => {
+                for draft in drafts{
+                    if let Some(bot_message_id) = draft.bot_message_id{
+                        edit(&telegram, draft.chat_id, bot_message_id,
+                             "Este borrador ha caducado", None).await;
+                    }
+                    if let Err(e) = draft.delete(&pool).await{
+                        println!("No he podido borrar el borrador {}: {}", draft.id, e);
+                    }
+                }
+            },
+            Err(e) => println!("No he podido leer los borradores: {}", e),
+        }
+        sleep(Duration::from_secs(60)).await;
+    }
+}
+
+async fn edit(telegram: &Telegram, chat_id: i64, message_id: i64, text: &str,
+        reply_markup: Option<&InlineKeyboardMarkup>){
+    if let Err(e) = telegram.edit_message_text(chat_id, message_id, text, reply_markup).await{
+        println!("No he podido editar el mensaje: {}", e);
+    }
+}
+
+async fn edit_prompt(telegram: &Telegram, draft: &Draft, text: &str,
+        reply_markup: Option<&InlineKeyboardMarkup>){
+    if let Some(bot_message_id) = draft.bot_message_id{
+        edit(telegram, draft.chat_id, bot_message_id, text, reply_markup).await;
+    }
+}
+
+async fn answer_callback(telegram: &Telegram, callback_query: &CallbackQuery, text: Option<&str>){
+    if let Err(e) = telegram.answer_callback_query(&callback_query.id, text).await{
+        println!("No he podido responder al callback: {}", e);
+    }
+}
@@ -0,0 +1,229 @@
+use actix_web::web;
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row, query, Error};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::update::{Message, ChatType};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelPost{
+    pub id: i64,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub episode: Option<String>,
+    pub content: String,
+    pub discussion_chat_id: Option<i64>,
+    pub discussion_message_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const COLUMNS: &str = "id, chat_id, message_id, episode, content,
+                       discussion_chat_id, discussion_message_id, created_at,
+                       updated_at";
+
+fn from_row(row: SqliteRow) -> ChannelPost{
+    ChannelPost{
+        id: row.get("id"),
+        chat_id: row.get("chat_id"),
+        message_id: row.get("message_id"),
+        episode: row.get("episode"),
+        content: row.get("content"),
+        discussion_chat_id: row.get("discussion_chat_id"),
+        discussion_message_id: row.get("discussion_message_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+pub fn episode(pattern: &Regex, text: &str) -> Option<String>{
+    pattern.captures(text)
+        .and_then(|captures| captures.get(1))
+        .map(|episode| episode.as_str().to_string())
+}
+
+fn content(message: &Message) -> String{
+    message.text.clone()
+        .or_else(|| message.caption.clone())
+        .unwrap_or_default()
+}
+
+impl ChannelPost{
+    pub async fn save(pool: &web::Data<SqlitePool>, message: &Message,
+            pattern: &Regex) -> Result<ChannelPost, Error>{
+        let timestamp = Utc::now().naive_utc();
+        let content = content(message);
+        let sql = format!("INSERT INTO channel_posts (chat_id, message_id, episode,
+                   content, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $5)
+                   ON CONFLICT(chat_id, message_id) DO UPDATE SET
+                   episode = excluded.episode, content = excluded.content,
+                   updated_at = excluded.updated_at RETURNING {}", COLUMNS);
+        query(&sql)
+            .bind(message.chat.id)
+            .bind(message.message_id)
+            .bind(episode(pattern, &content))
+            .bind(&content)
+            .bind(timestamp)
+            .map(from_row)
+            .fetch_one(pool.get_ref())
+            .await
+    }
+
+    pub async fn latest_episode(pool: &web::Data<SqlitePool>) -> Result<Option<i64>, Error>{
+        let row = query("SELECT MAX(CAST(episode AS INTEGER)) AS episode FROM channel_posts
+                         WHERE episode IS NOT NULL")
+            .fetch_one(pool.get_ref())
+            .await?;
+        Ok(row.get("episode"))
+    }
+
+    pub async fn read(pool: &web::Data<SqlitePool>, chat_id: i64,
+            message_id: i64) -> Result<Option<ChannelPost>, Error>{
+        let sql = format!("SELECT {} FROM channel_posts WHERE chat_id = $1
+                           AND message_id = $2", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(message_id)
+            .map(from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn link_discussion(pool: &web::Data<SqlitePool>, forward: &Message) -> Result<bool, Error>{
+        let origin = forward.forward_origin.as_ref()
+            .and_then(|origin| origin.chat.as_ref().zip(origin.message_id));
+        let (chat, message_id) = match origin{
+            Some(origin) if forward.is_automatic_forward => origin,
+            _ => return Ok(false),
+        };
+        let result = query("UPDATE channel_posts SET discussion_chat_id = $1,
+                            discussion_message_id = $2 WHERE chat_id = $3 AND message_id = $4")
+            .bind(forward.chat.id)
+            .bind(forward.message_id)
+            .bind(chat.id)
+            .bind(message_id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn read_by_discussion(pool: &web::Data<SqlitePool>, chat_id: i64,
+            message_id: i64) -> Result<Option<ChannelPost>, Error>{
+        let sql = format!("SELECT {} FROM channel_posts WHERE discussion_chat_id = $1
+                           AND discussion_message_id = $2", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(message_id)
+            .map(from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn episode_for(pool: &web::Data<SqlitePool>, message: &Message,
+            pattern: &Regex) -> Option<String>{
+        let reply = message.reply_to_message.as_ref().filter(|reply| reply.is_automatic_forward
+            || reply.sender_chat.as_ref()
+                .map(|chat| chat.kind == ChatType::Channel)
+                .unwrap_or(false));
+        let reply = match reply{
+            Some(reply) => reply,
+            None => {
+                let thread = message.message_thread_id?;
+                return match ChannelPost::read_by_discussion(pool, message.chat.id, thread).await{
+                    Ok(post) => post.and_then(|post| post.episode),
+                    Err(e) => {
+                        println!("No he podido leer la publicación del hilo: {}", e);
+                        None
+                    },
+                };
+            },
+        };
+        let origin = reply.forward_origin.as_ref()
+            .and_then(|origin| origin.chat.as_ref().zip(origin.message_id));
+        if let Some((chat, message_id)) = origin{
+            match ChannelPost::read(pool, chat.id, message_id).await{
+                Ok(Some(post)) if post.episode.is_some() => return post.episode,
+                Ok(_) => {},
+                Err(e) => println!("No he podido leer la publicación del canal: {}", e),
+            }
+        }
+        episode(pattern, &content(reply))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::channel::{ChannelPost, episode};
+    use crate::update::Message;
+    use regex::Regex;
+    use serde_json::json;
+    use crate::test_utils::memory_pool;
+
+    fn pattern() -> Regex{
+        Regex::new(r"(?i)(?:episodio|cap[ií]tulo|podcast)\s*(?:n[º°o]\.?\s*)?#?(\d+)").unwrap()
+    }
+
+    #[test]
+    fn finds_the_episode() {
+        let pattern = pattern();
+        assert_eq!(episode(&pattern, "Nuevo episodio 123: Rust").as_deref(), Some("123"));
+        assert_eq!(episode(&pattern, "CAPÍTULO #45 ya disponible").as_deref(), Some("45"));
+        assert_eq!(episode(&pattern, "Podcast nº 7").as_deref(), Some("7"));
+        assert_eq!(episode(&pattern, "Hoy no hay nada"), None);
+    }
+
+    #[actix_rt::test]
+    async fn comments_inherit_the_episode() {
+        let pool = memory_pool().await;
+        let pattern = pattern();
+        assert_eq!(ChannelPost::latest_episode(&pool).await.unwrap(), None);
+
+        let post: Message = serde_json::from_value(json!({
+            "message_id": 10, "date": 0,
+            "chat": {"id": -1001, "type": "channel", "title": "atareao"},
+            "text": "Episodio 500: los mejores comandos",
+        })).unwrap();
+        let saved = ChannelPost::save(&pool, &post, &pattern).await.unwrap();
+        assert_eq!(saved.episode.as_deref(), Some("500"));
+        let mut edited = post.clone();
+        edited.text = Some("Episodio 501: los mejores comandos".to_string());
+        let saved = ChannelPost::save(&pool, &edited, &pattern).await.unwrap();
+        assert_eq!(saved.episode.as_deref(), Some("501"));
+        assert_eq!(ChannelPost::latest_episode(&pool).await.unwrap(), Some(501));
+
+        let comment: Message = serde_json::from_value(json!({
+            "message_id": 3, "date": 0,
+            "from": {"id": 1, "is_bot": false, "first_name": "Lorenzo"},
+            "chat": {"id": -1002, "type": "supergroup", "title": "atareao charla"},
+            "text": "#comentario me gusta",
+            "reply_to_message": {
+                "message_id": 2, "date": 0, "is_automatic_forward": true,
+                "sender_chat": {"id": -1001, "type": "channel"},
+                "chat": {"id": -1002, "type": "supergroup"},
+                "forward_origin": {"type": "channel", "date": 0, "message_id": 10,
+                                   "chat": {"id": -1001, "type": "channel"}},
+                "text": "Sin número",
+            },
+        })).unwrap();
+        assert_eq!(ChannelPost::episode_for(&pool, &comment, &pattern).await.as_deref(), Some("501"));
+
+        let mut reply = comment.clone();
+        reply.reply_to_message.as_mut().unwrap().is_automatic_forward = false;
+        reply.reply_to_message.as_mut().unwrap().sender_chat = None;
+        assert_eq!(ChannelPost::episode_for(&pool, &reply, &pattern).await, None);
+
+        let answer: Message = serde_json::from_value(json!({
+            "message_id": 4, "message_thread_id": 2, "date": 0,
+            "from": {"id": 2, "is_bot": false, "first_name": "Juan"},
+            "chat": {"id": -1002, "type": "supergroup", "title": "atareao charla"},
+            "text": "#comentario a mí también",
+            "reply_to_message": comment,
+        })).unwrap();
+        assert_eq!(ChannelPost::episode_for(&pool, &answer, &pattern).await, None);
+        let forward = comment.reply_to_message.as_ref().unwrap();
+        assert!(ChannelPost::link_discussion(&pool, forward).await.unwrap());
+        assert!(!ChannelPost::link_discussion(&pool, &comment).await.unwrap());
+        assert_eq!(ChannelPost::episode_for(&pool, &answer, &pattern).await.as_deref(), Some("501"));
+    }
+}
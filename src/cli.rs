@@ -0,0 +1,75 @@
+use actix_web::web::Data;
+use sqlx::sqlite::SqlitePool;
+use chrono::Duration;
+use std::str::FromStr;
+
+use crate::{auth::{ApiKey, Scope}, config::Config, feedback::Feedback};
+
+const USAGE: &str = "Usage:
+    purge
+    keys create <name> <scope[,scope...]> [days]
+    keys revoke <prefix>
+    keys list
+
+Scopes: feedback:read, feedback:write, admin";
+
+pub async fn run(pool: Data<SqlitePool>, config: &Config, args: &[String]){
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice(){
+        ["purge"] => purge(&pool, config.database.retention_days).await,
+        ["keys", "create", name, scopes] => create_key(&pool, name, scopes, None).await,
+        ["keys", "create", name, scopes, days] => create_key(&pool, name, scopes, Some(days)).await,
+        ["keys", "revoke", prefix] => {
+            match ApiKey::revoke(&pool, prefix).await.expect("revoke failed"){
+                true => println!("Revoked key {}", prefix),
+                false => println!("No active key with prefix {}", prefix),
+            }
+        },
+        ["keys", "list"] => {
+            for api_key in ApiKey::read_all(&pool).await.expect("list failed"){
+                let scopes = api_key.scopes.iter()
+                    .map(|scope| scope.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(",");
+                let state = if api_key.is_active() {"active"} else {"inactive"};
+                println!("{}\t{}\t{}\t{}\t{}", api_key.prefix, api_key.name, scopes,
+                    state, api_key.last_used_at.map(|date| date.to_rfc3339())
+                        .unwrap_or_else(|| "never".to_string()));
+            }
+        },
+        _ => println!("{}", USAGE),
+    }
+}
+
+async fn purge(pool: &Data<SqlitePool>, days: i64){
+    let purged = Feedback::purge(pool, Duration::days(days))
+        .await
+        .expect("purge failed");
+    println!("Purged {} feedback deleted more than {} days ago", purged, days);
+}
+
+async fn create_key(pool: &Data<SqlitePool>, name: &str, scopes: &str, days: Option<&str>){
+    let scopes = match scopes.split(',')
+            .map(|scope| Scope::from_str(scope.trim()))
+            .collect::<Result<Vec<Scope>, String>>(){
+        Ok(scopes) => scopes,
+        Err(e) => {
+            println!("{}\n\n{}", e, USAGE);
+            return;
+        },
+    };
+    let expires_in = match days.map(|days| days.parse::<i64>()){
+        Some(Ok(days)) => Some(Duration::days(days)),
+        Some(Err(_)) => {
+            println!("Days must be a number\n\n{}", USAGE);
+            return;
+        },
+        None => None,
+    };
+    let (api_key, token) = ApiKey::mint(pool, name, &scopes, expires_in)
+        .await
+        .expect("create failed");
+    println!("Created key {} for {}. Store this token, it will not be shown again:",
+        api_key.prefix, api_key.name);
+    println!("{}", token);
+}
@@ -0,0 +1,528 @@
+use serde::Deserialize;
+use std::{collections::HashSet, env, fmt, fs};
+use toml::Value;
+use regex::Regex;
+
+#[derive(Debug)]
+pub enum ConfigError{
+    Io(String, std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ConfigError::Io(path, e) => write!(f, "Can not read config file {}: {}", path, e),
+            ConfigError::Parse(e) => write!(f, "Can not parse config file: {}", e),
+            ConfigError::Invalid(errors) => {
+                writeln!(f, "Invalid configuration:")?;
+                for error in errors{
+                    writeln!(f, "  - {}", error)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode{
+    Webhook,
+    Polling,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config{
+    pub telegram: TelegramConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub mattermost: MattermostConfig,
+    pub zinc: ZincConfig,
+    #[serde(default)]
+    pub attachments: AttachmentsConfig,
+    #[serde(default)]
+    pub categories: Categories,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramConfig{
+    pub token: String,
+    #[serde(default = "default_mode")]
+    pub mode: Mode,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    #[serde(default = "default_polling_timeout")]
+    pub polling_timeout: u64,
+    #[serde(default = "default_draft_timeout")]
+    pub draft_timeout: i64,
+    #[serde(default = "default_episode_pattern")]
+    pub episode_pattern: String,
+    #[serde(default = "default_telegram_upvotes")]
+    pub upvote_emoji: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig{
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+impl Default for ServerConfig{
+    fn default() -> Self{
+        Self{port: default_port()}
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseConfig{
+    pub url: String,
+    #[serde(default = "default_retention_days")]
+    pub retention_days: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MattermostConfig{
+    pub base_uri: String,
+    pub token: String,
+    #[serde(default)]
+    pub team: String,
+    #[serde(default)]
+    pub create_channels: bool,
+    pub mention_channel: Option<String>,
+    #[serde(default)]
+    pub webhook_tokens: Vec<String>,
+    pub command_token: Option<String>,
+    pub actions_url: Option<String>,
+    pub actions_secret: Option<String>,
+    #[serde(default = "default_reactions_interval")]
+    pub reactions_interval: u64,
+    #[serde(default = "default_mattermost_upvotes")]
+    pub upvote_emoji: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZincConfig{
+    pub base_url: String,
+    pub indice: String,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttachmentsConfig{
+    #[serde(default = "default_attachments_directory")]
+    pub directory: String,
+}
+
+impl Default for AttachmentsConfig{
+    fn default() -> Self{
+        Self{directory: default_attachments_directory()}
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Category{
+    pub name: String,
+    pub hashtag: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub reference: bool,
+    #[serde(default)]
+    pub voice: bool,
+    #[serde(default)]
+    pub votes: bool,
+    pub description: Option<String>,
+    pub channel: String,
+    #[serde(skip)]
+    pub channel_id: String,
+    pub zinc_type: Option<String>,
+    pub thanks: Option<String>,
+    pub empty: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Category{
+    pub fn hashtag(&self) -> &str{
+        self.hashtag.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn hashtags(&self) -> impl Iterator<Item = &str>{
+        std::iter::once(self.hashtag()).chain(self.aliases.iter().map(|alias| alias.as_str()))
+    }
+
+    pub fn help_text(&self) -> String{
+        self.render(self.description.as_deref()
+            .unwrap_or("Para enviar un {category}, utiliza `#{hashtag}`"), "")
+    }
+
+    pub fn zinc_type(&self) -> &str{
+        self.zinc_type.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn thanks_text(&self, user: &str) -> String{
+        self.render(self.thanks.as_deref()
+            .unwrap_or("Muchas gracias por tu {category} {user}"), user)
+    }
+
+    pub fn empty_text(&self, user: &str) -> String{
+        self.render(self.empty.as_deref()
+            .unwrap_or("Tienes que escribir `#{hashtag}` seguido del contenido, {user}"), user)
+    }
+
+    pub fn error_text(&self, user: &str) -> String{
+        self.render(self.error.as_deref()
+            .unwrap_or("Lo siento {user}, no he podido registrar tu {category}. Mira que está pasando @atareao!"), user)
+    }
+
+    fn render(&self, template: &str, user: &str) -> String{
+        template.replace("{user}", user)
+            .replace("{category}", &self.name)
+            .replace("{hashtag}", self.hashtag())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Categories(Vec<Category>);
+
+impl Categories{
+    pub fn get(&self, name: &str) -> Option<&Category>{
+        self.0.iter().find(|category| category.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Category>{
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Category>{
+        self.0.iter_mut()
+    }
+}
+
+fn default_mode() -> Mode{
+    Mode::Webhook
+}
+
+fn default_polling_timeout() -> u64{
+    30
+}
+
+fn default_draft_timeout() -> i64{
+    600
+}
+
+fn default_episode_pattern() -> String{
+    r"(?i)(?:episodio|cap[ií]tulo|podcast)\s*(?:n[º°o]\.?\s*)?#?(\d+)".to_string()
+}
+
+fn default_reactions_interval() -> u64{
+    300
+}
+
+fn default_telegram_upvotes() -> Vec<String>{
+    vec!["👍".to_string()]
+}
+
+fn default_mattermost_upvotes() -> Vec<String>{
+    vec!["+1".to_string(), "thumbsup".to_string()]
+}
+
+fn default_port() -> u16{
+    8080
+}
+
+fn default_attachments_directory() -> String{
+    "attachments".to_string()
+}
+
+fn default_retention_days() -> i64{
+    30
+}
+
+pub const MENTION: &str = "mencion";
+
+enum Kind{
+    Str,
+    Int,
+    Bool,
+    List,
+}
+
+const OVERRIDES: [(&str, &str, &str, Kind); 24] = [
+    ("TG_TOKEN", "telegram", "token", Kind::Str),
+    ("TG_MODE", "telegram", "mode", Kind::Str),
+    ("TG_WEBHOOK_URL", "telegram", "webhook_url", Kind::Str),
+    ("TG_WEBHOOK_SECRET", "telegram", "webhook_secret", Kind::Str),
+    ("TG_POLLING_TIMEOUT", "telegram", "polling_timeout", Kind::Int),
+    ("DRAFT_TIMEOUT", "telegram", "draft_timeout", Kind::Int),
+    ("TG_UPVOTE_EMOJI", "telegram", "upvote_emoji", Kind::List),
+    ("PORT", "server", "port", Kind::Int),
+    ("DATABASE_URL", "database", "url", Kind::Str),
+    ("RETENTION_DAYS", "database", "retention_days", Kind::Int),
+    ("MATTERMOST_BASE_URI", "mattermost", "base_uri", Kind::Str),
+    ("MATTERMOST_ACCESS_TOKEN", "mattermost", "token", Kind::Str),
+    ("MATTERMOST_TEAM", "mattermost", "team", Kind::Str),
+    ("MATTERMOST_CREATE_CHANNELS", "mattermost", "create_channels", Kind::Bool),
+    ("MATTERMOST_WEBHOOK_TOKENS", "mattermost", "webhook_tokens", Kind::List),
+    ("MATTERMOST_COMMAND_TOKEN", "mattermost", "command_token", Kind::Str),
+    ("MATTERMOST_ACTIONS_URL", "mattermost", "actions_url", Kind::Str),
+    ("MATTERMOST_ACTIONS_SECRET", "mattermost", "actions_secret", Kind::Str),
+    ("MATTERMOST_REACTIONS_INTERVAL", "mattermost", "reactions_interval", Kind::Int),
+    ("MATTERMOST_UPVOTE_EMOJI", "mattermost", "upvote_emoji", Kind::List),
+    ("ZINC_BASE_URL", "zinc", "base_url", Kind::Str),
+    ("ZINC_INDICE", "zinc", "indice", Kind::Str),
+    ("ZINC_TOKEN", "zinc", "token", Kind::Str),
+    ("ATTACHMENTS_DIR", "attachments", "directory", Kind::Str),
+];
+
+impl Config{
+    pub fn load(path: &str) -> Result<Config, ConfigError>{
+        let content = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+        Config::parse(&content, |name| env::var(name).ok())
+    }
+
+    pub fn parse<F>(content: &str, lookup: F) -> Result<Config, ConfigError>
+            where F: Fn(&str) -> Option<String>{
+        let mut value: Value = content.parse().map_err(ConfigError::Parse)?;
+        let mut errors = Vec::new();
+        if let Some(root) = value.as_table_mut(){
+            for (name, section, key, kind) in OVERRIDES.iter(){
+                let raw = match lookup(name){
+                    Some(raw) => raw,
+                    None => continue,
+                };
+                let item = match kind{
+                    Kind::Str => Value::String(raw),
+                    Kind::List => Value::Array(raw.split(',')
+                        .map(|item| item.trim())
+                        .filter(|item| !item.is_empty())
+                        .map(|item| Value::String(item.to_string()))
+                        .collect()),
+                    Kind::Bool => match raw.to_lowercase().as_str(){
+                        "true" | "1" | "yes" => Value::Boolean(true),
+                        "false" | "0" | "no" => Value::Boolean(false),
+                        _ => {
+                            errors.push(format!("{} must be true or false, found '{}'", name, raw));
+                            continue;
+                        },
+                    },
+                    Kind::Int => match raw.parse::<i64>(){
+                        Ok(number) => Value::Integer(number),
+                        Err(_) => {
+                            errors.push(format!("{} must be a number, found '{}'", name, raw));
+                            continue;
+                        },
+                    },
+                };
+                let table = root.entry(section.to_string())
+                    .or_insert_with(|| Value::Table(Default::default()));
+                if let Some(table) = table.as_table_mut(){
+                    table.insert(key.to_string(), item);
+                }
+            }
+        }
+        if !errors.is_empty(){
+            return Err(ConfigError::Invalid(errors));
+        }
+        let config: Config = value.try_into().map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError>{
+        let mut errors = Vec::new();
+        if self.telegram.token.trim().is_empty(){
+            errors.push("telegram.token can not be empty".to_string());
+        }
+        if self.telegram.mode == Mode::Webhook{
+            match &self.telegram.webhook_url{
+                Some(url) if url.starts_with("https://") => {},
+                Some(url) => errors.push(format!("telegram.webhook_url must be an https url, found '{}'", url)),
+                None => errors.push("telegram.webhook_url is required in webhook mode".to_string()),
+            }
+        }
+        match Regex::new(&self.telegram.episode_pattern){
+            Ok(pattern) if pattern.captures_len() > 1 => {},
+            Ok(_) => errors.push("telegram.episode_pattern needs a group capturing the episode number".to_string()),
+            Err(e) => errors.push(format!("telegram.episode_pattern is not a valid regex: {}", e)),
+        }
+        if self.telegram.draft_timeout <= 0{
+            errors.push("telegram.draft_timeout must be greater than 0".to_string());
+        }
+        if self.server.port == 0{
+            errors.push("server.port must be greater than 0".to_string());
+        }
+        if self.database.url.trim().is_empty(){
+            errors.push("database.url can not be empty".to_string());
+        }
+        if self.database.retention_days < 0{
+            errors.push("database.retention_days can not be negative".to_string());
+        }
+        if !self.mattermost.base_uri.starts_with("http://") &&
+                !self.mattermost.base_uri.starts_with("https://"){
+            errors.push(format!("mattermost.base_uri must be an http(s) url, found '{}'",
+                                self.mattermost.base_uri));
+        }
+        if let Some(url) = &self.mattermost.actions_url{
+            if !url.starts_with("http://") && !url.starts_with("https://"){
+                errors.push(format!("mattermost.actions_url must be an http(s) url, found '{}'", url));
+            }
+            if self.mattermost.actions_secret.as_deref().unwrap_or_default().trim().is_empty(){
+                errors.push("mattermost.actions_secret is required with mattermost.actions_url".to_string());
+            }
+        }
+        for (key, value) in [("mattermost.token", &self.mattermost.token),
+                             ("mattermost.team", &self.mattermost.team),
+                             ("zinc.base_url", &self.zinc.base_url),
+                             ("zinc.indice", &self.zinc.indice),
+                             ("zinc.token", &self.zinc.token)]{
+            if value.trim().is_empty(){
+                errors.push(format!("{} can not be empty", key));
+            }
+        }
+        if self.attachments.directory.trim().is_empty(){
+            errors.push("attachments.directory can not be empty".to_string());
+        }
+        if self.categories.0.is_empty(){
+            errors.push("at least one [[categories]] entry is required".to_string());
+        }
+        let mut names = HashSet::new();
+        let mut hashtags = HashSet::new();
+        for (index, category) in self.categories.iter().enumerate(){
+            if category.name.is_empty() ||
+                    !category.name.chars().all(|c| c.is_alphanumeric() || c == '_'){
+                errors.push(format!("categories[{}].name must be a single word, found '{}'",
+                                    index, category.name));
+            }
+            if category.name == MENTION{
+                errors.push(format!("category name '{}' is reserved for mentions", MENTION));
+            }
+            if !names.insert(category.name.as_str()){
+                errors.push(format!("category '{}' is defined twice", category.name));
+            }
+            for hashtag in category.hashtags(){
+                if hashtag.is_empty() || hashtag.starts_with('#') ||
+                        !hashtag.chars().all(|c| c.is_alphanumeric() || c == '_'){
+                    errors.push(format!("category '{}' has an invalid hashtag '{}', write it without '#'",
+                                        category.name, hashtag));
+                }
+                if !hashtags.insert(hashtag.to_lowercase()){
+                    errors.push(format!("hashtag '{}' is used by more than one category", hashtag));
+                }
+            }
+            if category.channel.trim().is_empty(){
+                errors.push(format!("category '{}' needs a Mattermost channel", category.name));
+            }
+        }
+        if errors.is_empty(){
+            Ok(())
+        }else{
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::config::{Config, ConfigError, Mode};
+
+    const SAMPLE: &str = include_str!("../config.sample.toml");
+
+    #[test]
+    fn parses_sample() {
+        let config = Config::parse(SAMPLE, |_| None).unwrap();
+        assert_eq!(config.telegram.mode, Mode::Webhook);
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.telegram.upvote_emoji, vec!["👍"]);
+        assert_eq!(config.mattermost.upvote_emoji, vec!["+1", "thumbsup"]);
+        let pregunta = config.categories.get("pregunta").unwrap();
+        assert_eq!(pregunta.channel, "atareao_pregunta");
+        assert_eq!(pregunta.thanks_text("@juan"), "Muchas gracias por tu pregunta @juan");
+        let comentario = config.categories.get("comentario").unwrap();
+        assert_eq!(comentario.zinc_type(), "comentario");
+        assert!(comentario.reference);
+        assert!(pregunta.voice && pregunta.reference && !comentario.voice);
+        assert!(pregunta.votes && !comentario.votes);
+        assert_eq!(comentario.hashtags().collect::<Vec<&str>>(), vec!["comentario", "comment"]);
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let config = Config::parse(SAMPLE, |name| match name{
+            "TG_MODE" => Some("polling".to_string()),
+            "PORT" => Some("9000".to_string()),
+            "TG_TOKEN" => Some("123:abc".to_string()),
+            "MATTERMOST_WEBHOOK_TOKENS" => Some("uno, dos,".to_string()),
+            "MATTERMOST_CREATE_CHANNELS" => Some("yes".to_string()),
+            "TG_UPVOTE_EMOJI" => Some("👍,❤".to_string()),
+            _ => None,
+        }).unwrap();
+        assert_eq!(config.telegram.mode, Mode::Polling);
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.telegram.token, "123:abc");
+        assert_eq!(config.mattermost.webhook_tokens, vec!["uno", "dos"]);
+        assert!(config.mattermost.create_channels);
+        assert_eq!(config.telegram.upvote_emoji, vec!["👍", "❤"]);
+
+        match Config::parse(SAMPLE, |name| (name == "PORT").then(|| "http".to_string())){
+            Err(ConfigError::Invalid(errors)) => assert_eq!(errors, vec![
+                "PORT must be a number, found 'http'".to_string()]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let content = r##"
+            [telegram]
+            token = ""
+            [database]
+            url = "sqlite:feedback.db"
+            [mattermost]
+            base_uri = "mattermost.local"
+            token = "secret"
+            actions_url = "https://bot.local/mattermost/action"
+            [zinc]
+            base_url = "zinc.local"
+            indice = "feedback"
+            token = "secret"
+            [[categories]]
+            name = "idea"
+            channel = "ideas"
+            [[categories]]
+            name = "otra"
+            hashtag = "#idea"
+            channel = ""
+            [[categories]]
+            name = "bug"
+            aliases = ["Idea"]
+            channel = "bugs"
+        "##;
+        let errors = match Config::parse(content, |_| None){
+            Err(ConfigError::Invalid(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(errors, vec![
+            "telegram.token can not be empty".to_string(),
+            "telegram.webhook_url is required in webhook mode".to_string(),
+            "mattermost.base_uri must be an http(s) url, found 'mattermost.local'".to_string(),
+            "mattermost.actions_secret is required with mattermost.actions_url".to_string(),
+            "mattermost.team can not be empty".to_string(),
+            "category 'otra' has an invalid hashtag '#idea', write it without '#'".to_string(),
+            "category 'otra' needs a Mattermost channel".to_string(),
+            "hashtag 'Idea' is used by more than one category".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn reports_missing_sections() {
+        match Config::parse("[telegram]\ntoken = \"x\"", |_| None){
+            Err(ConfigError::Parse(e)) => assert!(e.to_string().contains("database")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
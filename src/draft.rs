@@ -0,0 +1,160 @@
+use actix_web::web;
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row, query, Error};
+use chrono::{DateTime, Utc, Duration};
+
+use crate::{update::Message, message::get_user, feedback::Origin, attachment::FileRef};
+
+#[derive(Debug, Clone)]
+pub struct Draft{
+    pub id: i64,
+    pub category: String,
+    pub reference: String,
+    pub content: String,
+    pub username: String,
+    pub nickname: String,
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub message_thread_id: Option<i64>,
+    pub message_id: i64,
+    pub bot_message_id: Option<i64>,
+    pub attachment: Option<FileRef>,
+    pub created_at: DateTime<Utc>,
+}
+
+const COLUMNS: &str = "id, category, reference, content, username, nickname,
+                       user_id, chat_id, message_thread_id, message_id,
+                       bot_message_id, attachment, created_at";
+
+fn from_row(row: SqliteRow) -> Draft{
+    let attachment: Option<String> = row.get("attachment");
+    Draft{
+        id: row.get("id"),
+        category: row.get("category"),
+        reference: row.get("reference"),
+        content: row.get("content"),
+        username: row.get("username"),
+        nickname: row.get("nickname"),
+        user_id: row.get("user_id"),
+        chat_id: row.get("chat_id"),
+        message_thread_id: row.get("message_thread_id"),
+        message_id: row.get("message_id"),
+        bot_message_id: row.get("bot_message_id"),
+        attachment: attachment.and_then(|attachment| serde_json::from_str(&attachment).ok()),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl Draft{
+    pub async fn new_from(pool: &web::Data<SqlitePool>, category: &str, reference: &str,
+            content: &str, message: &Message, attachment: Option<&FileRef>) -> Result<Draft, Error>{
+        let created_at = Utc::now().naive_utc();
+        let (username, nickname) = get_user(message);
+        let user_id = message.from.as_ref().map(|from| from.id).unwrap_or_default();
+        let thread = if message.is_topic_message {message.message_thread_id} else {None};
+        let attachment = attachment.map(|attachment| serde_json::to_string(attachment).unwrap());
+        let sql = format!("INSERT INTO drafts (category, reference, content,
+                   username, nickname, user_id, chat_id, message_thread_id,
+                   message_id, attachment, created_at)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING {}", COLUMNS);
+        query(&sql)
+            .bind(category)
+            .bind(reference)
+            .bind(content)
+            .bind(username)
+            .bind(nickname)
+            .bind(user_id)
+            .bind(message.chat.id)
+            .bind(thread)
+            .bind(message.message_id)
+            .bind(attachment)
+            .bind(created_at)
+            .map(from_row)
+            .fetch_one(pool.get_ref())
+            .await
+    }
+
+    pub async fn read(pool: &web::Data<SqlitePool>, id: i64) -> Result<Option<Draft>, Error>{
+        let sql = format!("SELECT {} FROM drafts WHERE id = $1", COLUMNS);
+        query(&sql)
+            .bind(id)
+            .map(from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_expired(pool: &web::Data<SqlitePool>, timeout: i64) -> Result<Vec<Draft>, Error>{
+        let limit = (Utc::now() - Duration::seconds(timeout)).naive_utc();
+        let sql = format!("SELECT {} FROM drafts WHERE created_at < $1", COLUMNS);
+        query(&sql)
+            .bind(limit)
+            .map(from_row)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_by_origin(pool: &web::Data<SqlitePool>, chat_id: i64,
+            message_id: i64) -> Result<Vec<Draft>, Error>{
+        let sql = format!("SELECT {} FROM drafts WHERE chat_id = $1 AND message_id = $2
+                           ORDER BY id", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(message_id)
+            .map(from_row)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub fn origin(&self) -> Origin{
+        Origin{
+            chat_id: self.chat_id,
+            user_id: self.user_id,
+            message_id: self.message_id,
+            message_thread_id: self.message_thread_id,
+        }
+    }
+
+    pub fn is_expired(&self, timeout: i64) -> bool{
+        self.created_at + Duration::seconds(timeout) < Utc::now()
+    }
+
+    pub async fn set_category(&mut self, pool: &web::Data<SqlitePool>, category: &str) -> Result<(), Error>{
+        query("UPDATE drafts SET category = $1 WHERE id = $2")
+            .bind(category)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.category = category.to_string();
+        Ok(())
+    }
+
+    pub async fn set_content(&mut self, pool: &web::Data<SqlitePool>, reference: &str,
+            content: &str) -> Result<(), Error>{
+        query("UPDATE drafts SET reference = $1, content = $2 WHERE id = $3")
+            .bind(reference)
+            .bind(content)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.reference = reference.to_string();
+        self.content = content.to_string();
+        Ok(())
+    }
+
+    pub async fn set_bot_message(&mut self, pool: &web::Data<SqlitePool>, bot_message_id: i64) -> Result<(), Error>{
+        query("UPDATE drafts SET bot_message_id = $1 WHERE id = $2")
+            .bind(bot_message_id)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.bot_message_id = Some(bot_message_id);
+        Ok(())
+    }
+
+    pub async fn delete(&self, pool: &web::Data<SqlitePool>) -> Result<bool, Error>{
+        let result = query("DELETE FROM drafts WHERE id = $1")
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -1,9 +1,167 @@
 use actix_web::web;
-use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row, query, FromRow, Error};
+use sqlx::{sqlite::{SqlitePool, SqliteRow, SqliteArguments}, Row, query,
+          query::Query, FromRow, Error, Sqlite, Transaction};
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc, NaiveDateTime};
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use crate::{update::Message, config::MENTION};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Status{
+    New,
+    Triaged,
+    Planned,
+    Answered,
+    Rejected,
+    Duplicate,
+    Done,
+}
+
+impl Status{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            Status::New => "new",
+            Status::Triaged => "triaged",
+            Status::Planned => "planned",
+            Status::Answered => "answered",
+            Status::Rejected => "rejected",
+            Status::Duplicate => "duplicate",
+            Status::Done => "done",
+        }
+    }
+
+    pub fn next(&self) -> &'static [Status]{
+        match self{
+            Status::New => &[Status::Triaged, Status::Answered, Status::Rejected, Status::Duplicate],
+            Status::Triaged => &[Status::Planned, Status::Answered, Status::Rejected, Status::Duplicate],
+            Status::Planned => &[Status::Answered, Status::Done, Status::Rejected],
+            Status::Answered => &[Status::Done, Status::Triaged],
+            Status::Rejected => &[Status::Triaged],
+            Status::Duplicate => &[Status::Triaged],
+            Status::Done => &[],
+        }
+    }
+
+    pub fn can_transition_to(&self, to: Status) -> bool{
+        self.next().contains(&to)
+    }
+}
+
+impl fmt::Display for Status{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Status{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s{
+            "new" => Ok(Status::New),
+            "triaged" => Ok(Status::Triaged),
+            "planned" => Ok(Status::Planned),
+            "answered" => Ok(Status::Answered),
+            "rejected" => Ok(Status::Rejected),
+            "duplicate" => Ok(Status::Duplicate),
+            "done" => Ok(Status::Done),
+            _ => Err(format!("Unknown status {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TransitionError{
+    Invalid(Status, Status),
+    Sqlx(Error),
+}
+
+impl fmt::Display for TransitionError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            TransitionError::Invalid(from, to) =>
+                write!(f, "Can not change status from {} to {}", from, to),
+            TransitionError::Sqlx(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<Error> for TransitionError{
+    fn from(e: Error) -> Self{
+        TransitionError::Sqlx(e)
+    }
+}
 
 #[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct Transition{
+    pub id: i64,
+    pub feedback_id: i64,
+    pub from_status: Status,
+    pub to_status: Status,
+    pub actor: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub struct Edit{
+    pub id: i64,
+    pub feedback_id: i64,
+    pub reference: String,
+    pub content: String,
+    pub edited_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackData{
+    pub category: String,
+    #[serde(default)]
+    pub reference: String,
+    pub content: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub nickname: String,
+    #[serde(default)]
+    pub source: String,
+}
+
+impl FeedbackData{
+    pub fn new(category: &str, reference: &str, content: &str, username: &str, nickname: &str,
+            source: &str) -> FeedbackData{
+        Self{
+            category: category.to_string(),
+            reference: reference.to_string(),
+            content: content.to_string(),
+            username: username.to_string(),
+            nickname: nickname.to_string(),
+            source: source.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Origin{
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub message_id: i64,
+    pub message_thread_id: Option<i64>,
+}
+
+impl Origin{
+    pub fn from_message(message: &Message) -> Origin{
+        Origin{
+            chat_id: message.chat.id,
+            user_id: message.from.as_ref().map(|from| from.id).unwrap_or_default(),
+            message_id: message.message_id,
+            message_thread_id: if message.is_topic_message {message.message_thread_id} else {None},
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct Feedback{
     pub id: i64,
     pub category: String,
@@ -11,272 +169,1037 @@ pub struct Feedback{
     pub content: String,
     pub username: String,
     pub nickname: String,
-    pub applied: i64,
+    pub status: Status,
     pub source: String,
+    pub chat_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub message_id: Option<i64>,
+    pub message_thread_id: Option<i64>,
+    pub mattermost_post_id: Option<String>,
+    pub bot_message_id: Option<i64>,
+    pub parent_id: Option<i64>,
+    pub duplicate_of: Option<i64>,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub votes: Option<i64>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct SearchResult{
+    #[serde(flatten)]
+    pub feedback: Feedback,
+    pub rank: f64,
+    pub snippet: String,
+    pub highlight: String,
+}
+
+const COLUMNS: &str = "id, category, reference, content, username, nickname,
+                       status, source, chat_id, user_id, message_id,
+                       message_thread_id, mattermost_post_id, bot_message_id,
+                       parent_id, duplicate_of, created_at, updated_at,
+                       deleted_at";
+
+const VOTES: &str = "(SELECT COUNT(*) FROM votes WHERE votes.feedback_id = feedback.id)";
+
+const OPEN: &str = "status IN ('new', 'triaged', 'planned') AND deleted_at IS NULL
+                    AND parent_id IS NULL";
+
 impl Feedback {
-    pub async fn new() -> Self{
-        let timestamp = Utc::now();
-        Self{
-            id: -1,
-            category: "".to_string(),
-            reference: "".to_string(),
-            content: "".to_string(),
-            username: "".to_string(),
-            nickname: "".to_string(),
-            source: "".to_string(),
-            applied: 0,
-            created_at: timestamp,
-            updated_at: timestamp,
-        }
-    }
-
-    pub async fn new_from(pool: &web::Data<SqlitePool>, category: &str,
-            reference: &str, content: &str, username: &str, nickname: &str,
-            applied: i64, source: &str) -> Result<Feedback, Error>{
+    fn from_row(row: SqliteRow) -> Feedback{
+        Feedback{
+            id: row.get("id"),
+            category: row.get("category"),
+            reference: row.get("reference"),
+            content: row.get("content"),
+            username: row.get("username"),
+            nickname: row.get("nickname"),
+            source: row.get("source"),
+            status: row.get("status"),
+            chat_id: row.get("chat_id"),
+            user_id: row.get("user_id"),
+            message_id: row.get("message_id"),
+            message_thread_id: row.get("message_thread_id"),
+            mattermost_post_id: row.get("mattermost_post_id"),
+            bot_message_id: row.get("bot_message_id"),
+            parent_id: row.get("parent_id"),
+            duplicate_of: row.get("duplicate_of"),
+            votes: row.try_get("votes").ok(),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+            deleted_at: row.get("deleted_at"),
+        }
+    }
+
+    pub fn origin(&self) -> Option<Origin>{
+        match (self.chat_id, self.user_id, self.message_id){
+            (Some(chat_id), Some(user_id), Some(message_id)) => Some(Origin{
+                chat_id,
+                user_id,
+                message_id,
+                message_thread_id: self.message_thread_id,
+            }),
+            _ => None,
+        }
+    }
+
+    pub async fn new_from(pool: &web::Data<SqlitePool>, data: &FeedbackData,
+            origin: Option<&Origin>) -> Result<Feedback, Error>{
         let timestamp = Utc::now().naive_utc();
-        let sql = "INSERT INTO feedback (category, reference, content,
- username, nickname, applied, source, created_at, updated_at)
- VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id, category, reference,
- content, username, nickname, source, applied, created_at, updated_at;";
-        query(sql)
-            .bind(category)
-            .bind(reference)
-            .bind(content)
-            .bind(username)
-            .bind(nickname)
-            .bind(applied)
-            .bind(source)
+        let sql = format!("INSERT INTO feedback (category, reference, content,
+ username, nickname, status, source, chat_id, user_id, message_id,
+ message_thread_id, created_at, updated_at)
+ VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING {};", COLUMNS);
+        query(&sql)
+            .bind(&data.category)
+            .bind(&data.reference)
+            .bind(&data.content)
+            .bind(&data.username)
+            .bind(&data.nickname)
+            .bind(Status::New)
+            .bind(&data.source)
+            .bind(origin.map(|origin| origin.chat_id))
+            .bind(origin.map(|origin| origin.user_id))
+            .bind(origin.map(|origin| origin.message_id))
+            .bind(origin.and_then(|origin| origin.message_thread_id))
             .bind(timestamp)
             .bind(timestamp)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
+            .map(Feedback::from_row)
             .fetch_one(pool.get_ref())
             .await
     }
 
     pub async fn update_from(pool: &web::Data<SqlitePool>, id: i64,
-            category: &str, reference: &str, content: &str, username: &str,
-            nickname: &str, applied: i64, source: &str) -> Result<Feedback, Error>{
+            data: &FeedbackData) -> Result<Feedback, Error>{
         let updated_at = Utc::now().naive_utc();
-        let sql = "UPDATE feedback SET category=?, reference=?, content=?,
-              username=?, nickname=?, applied=?, source=?, updated_at=?
-              WHERE id=? RETURNING id, category, reference, content, username,
-              nickname, source, applied, created_at, updated_at";
-        query(sql)
-            .bind(category)
-            .bind(reference)
-            .bind(content)
-            .bind(username)
-            .bind(nickname)
-            .bind(applied)
-            .bind(source)
+        let sql = format!("UPDATE feedback SET category=?, reference=?, content=?,
+              username=?, nickname=?, source=?, updated_at=?
+              WHERE id=? AND deleted_at IS NULL RETURNING {}", COLUMNS);
+        query(&sql)
+            .bind(&data.category)
+            .bind(&data.reference)
+            .bind(&data.content)
+            .bind(&data.username)
+            .bind(&data.nickname)
+            .bind(&data.source)
             .bind(updated_at)
             .bind(id)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
+            .map(Feedback::from_row)
             .fetch_one(pool.get_ref())
             .await
     }
 
     pub async fn delete(&self, pool: &web::Data<SqlitePool>) -> Result<bool, Error>{
         if self.id > -1{
-            query(r#"DELETE FROM feedback WHERE id = $1;"#)
+            let deleted_at = Utc::now().naive_utc();
+            let result = query("UPDATE feedback SET deleted_at = $1
+                                WHERE id = $2 AND deleted_at IS NULL;")
+                .bind(deleted_at)
                 .bind(self.id)
                 .execute(pool.get_ref())
                 .await?;
-            return Ok(true);
+            return Ok(result.rows_affected() > 0);
         }
         Ok(false)
     }
-    pub async fn load(pool: &web::Data<SqlitePool>, id: i64) -> Result<Self, Error>{
-        let sql = "SELECT id, category, reference, content, username, nickname,
-                   source, applied, created_at, updated_at FROM feedback
-                   WHERE id = $1;";
-        query(sql)
+
+    pub async fn restore(pool: &web::Data<SqlitePool>, id: i64) -> Result<Feedback, Error>{
+        let sql = format!("UPDATE feedback SET deleted_at = NULL
+                           WHERE id = $1 AND deleted_at IS NOT NULL
+                           RETURNING {}", COLUMNS);
+        query(&sql)
             .bind(id)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
+            .map(Feedback::from_row)
             .fetch_one(pool.get_ref())
             .await
     }
 
-    pub async fn save(&mut self, pool: &web::Data<SqlitePool>) -> Result<Feedback, Error>{
-        self.updated_at = Utc::now();
-        let sql = if self.id > -1{
-            "UPDATE feedback SET category=$1, reference=$2, content=$3,
-             username=$4, nickname=$5, applied=$6, source=$7, updated_at=$8
-             WHERE id=$9 RETURNING id, category, reference, content, username,
-             nickname, source, applied, created_at, updated_at;"
-        }else{
-            "INSERT INTO feedback (category, reference, content, username,
-             nickname, applied, source, created_at, updated_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING id, category, reference,
-             content, username, nickname, source, applied, created_at,
-             updated_at;"
-        };
-        let mut mquery = query(sql)
-            .bind(&self.category)
-            .bind(&self.reference)
-            .bind(&self.content)
-            .bind(&self.username)
-            .bind(&self.nickname)
-            .bind(&self.applied)
-            .bind(&self.source)
-            .bind(&self.updated_at);
-        if self.id > -1{
-            mquery = mquery
-                .bind(&self.id)
-        }
-        mquery
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
+    pub async fn purge(pool: &web::Data<SqlitePool>, retention: Duration) -> Result<u64, Error>{
+        let limit = (Utc::now() - retention).naive_utc();
+        let mut tx = pool.begin().await?;
+        let paths: Vec<String> = query("SELECT path FROM attachments WHERE feedback_id IN
+                                        (SELECT id FROM feedback WHERE deleted_at < $1)")
+            .bind(limit)
+            .map(|row: SqliteRow| row.get("path"))
+            .fetch_all(&mut tx)
+            .await?;
+        let result = query("DELETE FROM feedback WHERE deleted_at < $1")
+            .bind(limit)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        for path in paths.iter().map(Path::new){
+            if let Err(e) = fs::remove_file(path){
+                println!("No he podido borrar el adjunto {}: {}", path.display(), e);
+            }
+            if let Some(folder) = path.parent(){
+                if fs::read_dir(folder).map(|mut entries| entries.next().is_none()).unwrap_or(false){
+                    if let Err(e) = fs::remove_dir(folder){
+                        println!("No he podido borrar la carpeta {}: {}", folder.display(), e);
+                    }
+                }
+            }
+        }
+        Ok(result.rows_affected())
+    }
+
+    pub async fn read(pool: &web::Data<SqlitePool>, id: i64) -> Result<Feedback, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE id = $1
+                           AND deleted_at IS NULL", COLUMNS);
+        query(&sql)
+            .bind(id)
+            .map(Feedback::from_row)
             .fetch_one(pool.get_ref())
             .await
     }
 
-    pub async fn create(pool: web::Data<SqlitePool>, category: &str,
-            reference: &str, content: &str, username: &str,
-            nickname: &str, source: &str) -> Result<Feedback, Error>{
-        let applied:i64 = 0;
-        let created_at = Utc::now();
-        let updated_at = &created_at;
-        let sql = "INSERT INTO feedback (category, reference, content,
-                   username, nickname, applied, source, created_at,
-                   updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-                   RETURNING id, category, reference, content, username,
-                   nickname, source, applied, created_at, updated_at;";
-        query(sql)
-            .bind(category)
-            .bind(reference)
-            .bind(content)
-            .bind(username)
-            .bind(nickname)
-            .bind(applied)
-            .bind(source)
-            .bind(created_at)
-            .bind(updated_at)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
+    pub async fn search(pool: &web::Data<SqlitePool>, terms: &str, limit: i64) -> Result<Vec<SearchResult>, Error>{
+        let sql = format!("SELECT {}, rank, snippet, highlight FROM feedback
+                   JOIN (SELECT rowid, bm25(feedback_fts) AS rank,
+                         snippet(feedback_fts, 2, '<mark>', '</mark>', '…', 16) AS snippet,
+                         highlight(feedback_fts, 2, '<mark>', '</mark>') AS highlight
+                         FROM feedback_fts WHERE feedback_fts MATCH $1) AS results
+                   ON feedback.id = results.rowid WHERE deleted_at IS NULL
+                   ORDER BY rank LIMIT $2", COLUMNS);
+        query(&sql)
+            .bind(fts_query(terms))
+            .bind(limit)
+            .map(|row: SqliteRow| SearchResult{
+                rank: row.get("rank"),
+                snippet: row.get("snippet"),
+                highlight: row.get("highlight"),
+                feedback: Feedback::from_row(row),
             })
-            .fetch_one(pool.get_ref())
+            .fetch_all(pool.get_ref())
             .await
     }
 
-    pub async fn read(pool: &web::Data<SqlitePool>, id: i64) -> Result<Feedback, Error>{
-        let sql = "SELECT id, category, reference, content, username, nickname,
-                   applied, source, created_at, updated_at FROM feedback
-                   WHERE id = $1";
+    async fn apply_transition(&self, tx: &mut Transaction<'_, Sqlite>, to: Status,
+            actor: &str) -> Result<Feedback, TransitionError>{
+        if !self.status.can_transition_to(to){
+            return Err(TransitionError::Invalid(self.status, to));
+        }
+        let updated_at = Utc::now().naive_utc();
+        let sql = format!("UPDATE feedback SET status = $1, updated_at = $2
+                   WHERE id = $3 AND status = $4 AND deleted_at IS NULL
+                   RETURNING {}", COLUMNS);
+        let feedback = query(&sql)
+            .bind(to)
+            .bind(updated_at)
+            .bind(self.id)
+            .bind(self.status)
+            .map(Feedback::from_row)
+            .fetch_one(&mut *tx)
+            .await?;
+        let sql = "INSERT INTO feedback_transitions (feedback_id, from_status,
+                   to_status, actor, created_at) VALUES ($1, $2, $3, $4, $5)";
         query(sql)
-            .bind(id)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
-            .fetch_one(pool.get_ref())
+            .bind(self.id)
+            .bind(self.status)
+            .bind(to)
+            .bind(actor)
+            .bind(updated_at)
+            .execute(&mut *tx)
+            .await?;
+        Ok(feedback)
+    }
+
+    pub async fn transition(&self, pool: &web::Data<SqlitePool>, to: Status,
+            actor: &str) -> Result<Feedback, TransitionError>{
+        let mut tx = pool.begin().await?;
+        let feedback = self.apply_transition(&mut tx, to, actor).await?;
+        tx.commit().await?;
+        Ok(feedback)
+    }
+
+    pub async fn plan(&self, pool: &web::Data<SqlitePool>, reference: &str,
+            actor: &str) -> Result<Feedback, TransitionError>{
+        let mut tx = pool.begin().await?;
+        let mut feedback = self.clone();
+        if reference != self.reference{
+            let sql = format!("UPDATE feedback SET reference = $1, updated_at = $2
+                       WHERE id = $3 AND deleted_at IS NULL RETURNING {}", COLUMNS);
+            feedback = query(&sql)
+                .bind(reference)
+                .bind(Utc::now().naive_utc())
+                .bind(self.id)
+                .map(Feedback::from_row)
+                .fetch_one(&mut tx)
+                .await?;
+        }
+        if feedback.status == Status::New{
+            feedback = feedback.apply_transition(&mut tx, Status::Triaged, actor).await?;
+        }
+        let feedback = feedback.apply_transition(&mut tx, Status::Planned, actor).await?;
+        tx.commit().await?;
+        Ok(feedback)
+    }
+
+    pub async fn mark_duplicate(&self, pool: &web::Data<SqlitePool>, original: i64,
+            actor: &str) -> Result<Feedback, TransitionError>{
+        let mut tx = pool.begin().await?;
+        self.apply_transition(&mut tx, Status::Duplicate, actor).await?;
+        let sql = format!("UPDATE feedback SET duplicate_of = $1 WHERE id = $2
+                   RETURNING {}", COLUMNS);
+        let feedback = query(&sql)
+            .bind(original)
+            .bind(self.id)
+            .map(Feedback::from_row)
+            .fetch_one(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(feedback)
+    }
+
+    pub async fn read_by_origin(pool: &web::Data<SqlitePool>, chat_id: i64,
+            message_id: i64) -> Result<Vec<Feedback>, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE chat_id = $1 AND
+                   message_id = $2 AND deleted_at IS NULL ORDER BY id", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(message_id)
+            .map(Feedback::from_row)
+            .fetch_all(pool.get_ref())
             .await
     }
 
-    pub async fn read_all(pool: web::Data<SqlitePool>) -> Result<Vec<Feedback>, Error>{
-        let sql = "SELECT id, category, reference, content, username, nickname,
-                   applied, source, created_at, updated_at FROM feedback";
-        query(sql)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
+    pub async fn top(pool: &web::Data<SqlitePool>, categories: &[&str],
+            limit: i64) -> Result<Vec<Feedback>, Error>{
+        if categories.is_empty(){
+            return Ok(Vec::new());
+        }
+        let sql = format!("SELECT {columns}, {votes} AS votes FROM feedback
+                   WHERE {open} AND category IN ({categories})
+                   AND {votes} > 0 ORDER BY votes DESC, created_at LIMIT ?",
+                   columns = COLUMNS, votes = VOTES, open = OPEN,
+                   categories = vec!["?"; categories.len()].join(", "));
+        let mut top = query(&sql);
+        for category in categories{
+            top = top.bind(*category);
+        }
+        top.bind(limit)
+            .map(Feedback::from_row)
             .fetch_all(pool.get_ref())
             .await
     }
 
-    pub async fn update(pool: web::Data<SqlitePool>, id: i64) -> Result<Feedback, Error>{
-        let updated_at = Utc::now().naive_utc();
-        let sql = "UPDATE feedback SET applied = 1, updated_at = $1
-                   WHERE id = $2";
+    pub async fn read_open_posts(pool: &web::Data<SqlitePool>) -> Result<Vec<Feedback>, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE {} AND category != $1
+                   AND mattermost_post_id IS NOT NULL ORDER BY id", COLUMNS, OPEN);
+        query(&sql)
+            .bind(MENTION)
+            .map(Feedback::from_row)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_by_post_id(pool: &web::Data<SqlitePool>,
+            post_id: &str) -> Result<Option<Feedback>, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE mattermost_post_id = $1
+                   AND deleted_at IS NULL", COLUMNS);
+        query(&sql)
+            .bind(post_id)
+            .map(Feedback::from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_by_reply(pool: &web::Data<SqlitePool>, chat_id: i64,
+            message_id: i64) -> Result<Option<Feedback>, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE chat_id = $1 AND
+                   (message_id = $2 OR bot_message_id = $2) AND deleted_at IS NULL
+                   ORDER BY id LIMIT 1", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(message_id)
+            .map(Feedback::from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn read_by_bot_message(pool: &web::Data<SqlitePool>, chat_id: i64,
+            bot_message_id: i64) -> Result<Option<Feedback>, Error>{
+        let sql = format!("SELECT {} FROM feedback WHERE chat_id = $1 AND
+                   bot_message_id = $2 AND deleted_at IS NULL", COLUMNS);
+        query(&sql)
+            .bind(chat_id)
+            .bind(bot_message_id)
+            .map(Feedback::from_row)
+            .fetch_optional(pool.get_ref())
+            .await
+    }
+
+    pub async fn thread_root(&self, pool: &web::Data<SqlitePool>) -> Result<Feedback, Error>{
+        match self.parent_id{
+            Some(parent_id) => Feedback::read(pool, parent_id).await,
+            None => Ok(self.clone()),
+        }
+    }
+
+    pub async fn set_bot_message(&mut self, pool: &web::Data<SqlitePool>, bot_message_id: i64) -> Result<(), Error>{
+        query("UPDATE feedback SET bot_message_id = $1 WHERE id = $2")
+            .bind(bot_message_id)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.bot_message_id = Some(bot_message_id);
+        Ok(())
+    }
+
+    pub async fn set_parent(&mut self, pool: &web::Data<SqlitePool>, parent_id: i64) -> Result<(), Error>{
+        query("UPDATE feedback SET parent_id = $1 WHERE id = $2")
+            .bind(parent_id)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.parent_id = Some(parent_id);
+        Ok(())
+    }
+
+    pub async fn set_post_id(&mut self, pool: &web::Data<SqlitePool>, post_id: &str) -> Result<(), Error>{
+        query("UPDATE feedback SET mattermost_post_id = $1 WHERE id = $2")
+            .bind(post_id)
+            .bind(self.id)
+            .execute(pool.get_ref())
+            .await?;
+        self.mattermost_post_id = Some(post_id.to_string());
+        Ok(())
+    }
+
+    pub async fn edit(&self, pool: &web::Data<SqlitePool>, reference: &str,
+            content: &str) -> Result<Feedback, Error>{
+        let edited_at = Utc::now().naive_utc();
+        let mut tx = pool.begin().await?;
+        let sql = "INSERT INTO feedback_edits (feedback_id, reference, content,
+                   edited_at) VALUES ($1, $2, $3, $4)";
         query(sql)
-            .bind(updated_at)
-            .bind(id)
-            .map(|row: SqliteRow| Feedback{
-                id: row.get("id"),
-                category: row.get("category"),
-                reference: row.get("reference"),
-                content: row.get("content"),
-                username: row.get("username"),
-                nickname: row.get("nickname"),
-                source: row.get("source"),
-                applied: row.get("applied"),
-                created_at: row.get("created_at"),
-                updated_at: row.get("updated_at"),
-            })
-            .fetch_one(pool.get_ref())
+            .bind(self.id)
+            .bind(&self.reference)
+            .bind(&self.content)
+            .bind(edited_at)
+            .execute(&mut tx)
+            .await?;
+        let sql = format!("UPDATE feedback SET reference = $1, content = $2,
+                   updated_at = $3 WHERE id = $4 RETURNING {}", COLUMNS);
+        let feedback = query(&sql)
+            .bind(reference)
+            .bind(content)
+            .bind(edited_at)
+            .bind(self.id)
+            .map(Feedback::from_row)
+            .fetch_one(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(feedback)
+    }
+
+    pub async fn edits(&self, pool: &web::Data<SqlitePool>) -> Result<Vec<Edit>, Error>{
+        let sql = "SELECT id, feedback_id, reference, content, edited_at
+                   FROM feedback_edits WHERE feedback_id = $1
+                   ORDER BY edited_at, id";
+        sqlx::query_as::<_, Edit>(sql)
+            .bind(self.id)
+            .fetch_all(pool.get_ref())
             .await
     }
+
+    pub async fn transitions(&self, pool: &web::Data<SqlitePool>) -> Result<Vec<Transition>, Error>{
+        let sql = "SELECT id, feedback_id, from_status, to_status, actor,
+                   created_at FROM feedback_transitions WHERE feedback_id = $1
+                   ORDER BY created_at, id";
+        sqlx::query_as::<_, Transition>(sql)
+            .bind(self.id)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField{
+    Id,
+    CreatedAt,
+    UpdatedAt,
+    Category,
+    Status,
+    Votes,
+}
+
+impl SortField{
+    fn column(&self) -> &'static str{
+        match self{
+            SortField::Id => "id",
+            SortField::CreatedAt => "created_at",
+            SortField::UpdatedAt => "updated_at",
+            SortField::Category => "category",
+            SortField::Status => "status",
+            SortField::Votes => VOTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction{
+    Asc,
+    Desc,
+}
+
+impl Direction{
+    fn reverse(&self) -> Direction{
+        match self{
+            Direction::Asc => Direction::Desc,
+            Direction::Desc => Direction::Asc,
+        }
+    }
+
+    fn keyword(&self) -> &'static str{
+        match self{
+            Direction::Asc => "ASC",
+            Direction::Desc => "DESC",
+        }
+    }
+
+    fn operator(&self) -> &'static str{
+        match self{
+            Direction::Asc => ">",
+            Direction::Desc => "<",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor{
+    pub forward: bool,
+    pub id: i64,
+    pub value: String,
+}
+
+impl Cursor{
+    pub fn encode(&self) -> String{
+        let raw = format!("{}:{}", self.id, self.value);
+        let hex: String = raw.bytes().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}{}", if self.forward {"n"} else {"p"}, hex)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Cursor>{
+        let forward = match cursor.get(..1)?{
+            "n" => true,
+            "p" => false,
+            _ => return None,
+        };
+        let hex = &cursor[1..];
+        if hex.len() % 2 != 0{
+            return None;
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()?;
+        let raw = String::from_utf8(bytes).ok()?;
+        let (id, value) = raw.split_once(':')?;
+        Some(Cursor{
+            forward,
+            id: id.parse().ok()?,
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page{
+    pub items: Vec<Feedback>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+enum Param{
+    Text(String),
+    Integer(i64),
+    Timestamp(NaiveDateTime),
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct FeedbackQuery{
+    pub category: Option<String>,
+    pub reference: Option<String>,
+    pub source: Option<String>,
+    pub status: Option<Status>,
+    pub username: Option<String>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub updated_from: Option<DateTime<Utc>>,
+    pub updated_to: Option<DateTime<Utc>>,
+    pub deleted: Option<bool>,
+    pub sort: Option<SortField>,
+    pub direction: Option<Direction>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+impl FeedbackQuery{
+    fn sort(&self) -> SortField{
+        self.sort.unwrap_or(SortField::CreatedAt)
+    }
+
+    fn direction(&self) -> Direction{
+        self.direction.unwrap_or(Direction::Desc)
+    }
+
+    pub fn limit(&self) -> i64{
+        self.limit.unwrap_or(20).clamp(1, 100)
+    }
+
+    fn filters(&self) -> (Vec<String>, Vec<Param>){
+        let mut conditions = vec![
+            if self.deleted.unwrap_or(false){
+                "deleted_at IS NOT NULL".to_string()
+            }else{
+                "deleted_at IS NULL".to_string()
+            }
+        ];
+        let mut params = Vec::new();
+        let texts = [
+            ("category", &self.category),
+            ("reference", &self.reference),
+            ("source", &self.source),
+        ];
+        for (column, value) in texts{
+            if let Some(value) = value{
+                conditions.push(format!("{} = ?", column));
+                params.push(Param::Text(value.clone()));
+            }
+        }
+        if let Some(status) = self.status{
+            conditions.push("status = ?".to_string());
+            params.push(Param::Text(status.as_str().to_string()));
+        }
+        if let Some(username) = &self.username{
+            conditions.push("(username = ? OR nickname = ?)".to_string());
+            params.push(Param::Text(username.clone()));
+            params.push(Param::Text(username.trim_start_matches('@').to_string()));
+        }
+        let dates = [
+            ("created_at >= ?", &self.created_from),
+            ("created_at <= ?", &self.created_to),
+            ("updated_at >= ?", &self.updated_from),
+            ("updated_at <= ?", &self.updated_to),
+        ];
+        for (condition, value) in dates{
+            if let Some(value) = value{
+                conditions.push(condition.to_string());
+                params.push(Param::Timestamp(value.naive_utc()));
+            }
+        }
+        (conditions, params)
+    }
+
+    fn sort_param(&self, value: &str) -> Param{
+        match self.sort(){
+            SortField::Id | SortField::Votes => Param::Integer(value.parse().unwrap_or_default()),
+            _ => Param::Text(value.to_string()),
+        }
+    }
+
+    fn to_sql(&self, cursor: Option<&Cursor>) -> (String, Vec<Param>){
+        let (mut conditions, mut params) = self.filters();
+        let column = self.sort().column();
+        let forward = cursor.map(|cursor| cursor.forward).unwrap_or(true);
+        let direction = if forward {self.direction()} else {self.direction().reverse()};
+        if let Some(cursor) = cursor{
+            conditions.push(format!("({col} {op} ? OR ({col} = ? AND id {op} ?))",
+                                    col = column, op = direction.operator()));
+            params.push(self.sort_param(&cursor.value));
+            params.push(self.sort_param(&cursor.value));
+            params.push(Param::Integer(cursor.id));
+        }
+        let sql = format!("SELECT {columns}, {votes} AS votes, CAST({col} AS TEXT) AS sort_value
+                           FROM feedback WHERE {filter} ORDER BY {col} {dir}, id {dir}
+                           LIMIT ?",
+                          columns = COLUMNS, votes = VOTES, col = column,
+                          filter = conditions.join(" AND "),
+                          dir = direction.keyword());
+        params.push(Param::Integer(self.limit() + 1));
+        (sql, params)
+    }
+
+    pub async fn fetch(&self, pool: &web::Data<SqlitePool>, cursor: Option<&Cursor>) -> Result<Page, Error>{
+        let (sql, params) = self.to_sql(cursor);
+        let mut mquery: Query<Sqlite, SqliteArguments> = query(&sql);
+        for param in params{
+            mquery = match param{
+                Param::Text(value) => mquery.bind(value),
+                Param::Integer(value) => mquery.bind(value),
+                Param::Timestamp(value) => mquery.bind(value),
+            };
+        }
+        let mut rows: Vec<(String, Feedback)> = mquery
+            .map(|row: SqliteRow| (row.get("sort_value"), Feedback::from_row(row)))
+            .fetch_all(pool.get_ref())
+            .await?;
+        let limit = self.limit() as usize;
+        let forward = cursor.map(|cursor| cursor.forward).unwrap_or(true);
+        let more = rows.len() > limit;
+        rows.truncate(limit);
+        if !forward{
+            rows.reverse();
+        }
+        let cursor_for = |forward: bool, (value, feedback): &(String, Feedback)| Cursor{
+            forward,
+            id: feedback.id,
+            value: value.clone(),
+        }.encode();
+        let (has_next, has_prev) = if forward {(more, cursor.is_some())} else {(true, more)};
+        let next = rows.last().filter(|_| has_next).map(|row| cursor_for(true, row));
+        let prev = rows.first().filter(|_| has_prev).map(|row| cursor_for(false, row));
+        Ok(Page{
+            items: rows.into_iter().map(|(_, feedback)| feedback).collect(),
+            next,
+            prev,
+        })
+    }
+}
+
+fn fts_query(terms: &str) -> String{
+    terms.split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::feedback::{Feedback, FeedbackData, FeedbackQuery, Cursor, SortField, Direction,
+                          Status, Origin, fts_query};
+    use crate::attachment::{Attachment, FileRef};
+    use actix_web::web;
+    use chrono::Duration;
+    use crate::test_utils::memory_pool;
+    use sqlx::sqlite::SqlitePool;
+    use std::str::FromStr;
+
+    #[test]
+    fn status_transitions() {
+        assert!(Status::New.can_transition_to(Status::Triaged));
+        assert!(Status::Triaged.can_transition_to(Status::Planned));
+        assert!(Status::Planned.can_transition_to(Status::Done));
+        assert!(Status::Rejected.can_transition_to(Status::Triaged));
+        assert!(!Status::New.can_transition_to(Status::New));
+        assert!(!Status::New.can_transition_to(Status::Done));
+        assert!(Status::Done.next().is_empty());
+    }
+
+    #[test]
+    fn status_from_str() {
+        for status in [Status::New, Status::Triaged, Status::Planned,
+                Status::Answered, Status::Rejected, Status::Duplicate,
+                Status::Done]{
+            assert_eq!(Status::from_str(status.as_str()), Ok(status));
+        }
+        assert!(Status::from_str("applied").is_err());
+    }
+
+    #[test]
+    fn fts_query_quotes_terms() {
+        assert_eq!(fts_query("rust  linux"), r#""rust"* "linux"*"#);
+        assert_eq!(fts_query(r#"c++ "hola"#), r#""c++"* """hola"*"#);
+        assert_eq!(fts_query("   "), "");
+    }
+
+    #[actix_rt::test]
+    async fn search_follows_inserts_and_updates() {
+        let pool = memory_pool().await;
+        let rust = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "Un capítulo sobre Rust",
+            "Lorenzo", "atareao", "Telegram"), None).await.unwrap();
+        Feedback::new_from(&pool, &FeedbackData::new("pregunta", "", "¿Qué distribución usas?",
+            "Lorenzo", "atareao", "Telegram"), None).await.unwrap();
+
+        let results = Feedback::search(&pool, "capitulo", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].feedback.id, rust.id);
+        assert!(results[0].snippet.contains("<mark>capítulo</mark>"));
+
+        Feedback::update_from(&pool, rust.id, &FeedbackData::new("idea", "", "Un capítulo sobre Go",
+            "Lorenzo", "atareao", "Telegram")).await.unwrap();
+        assert!(Feedback::search(&pool, "rust", 10).await.unwrap().is_empty());
+        assert_eq!(Feedback::search(&pool, "go", 10).await.unwrap().len(), 1);
+    }
+
+    async fn seed(pool: &web::Data<SqlitePool>) -> Vec<Feedback>{
+        let mut items = Vec::new();
+        for (category, reference, username) in [
+                ("idea", "", "ana"), ("pregunta", "", "luis"),
+                ("comentario", "123", "ana"), ("idea", "", "marta"),
+                ("comentario", "124", "luis")]{
+            items.push(Feedback::new_from(pool, &FeedbackData::new(category, reference, "contenido",
+                username, username, "Telegram"), None).await.unwrap());
+        }
+        items
+    }
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = Cursor{
+            forward: false,
+            id: 42,
+            value: "2022-09-04 19:18:26.123:ñ".to_string(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("x00"), None);
+        assert_eq!(Cursor::decode("n0"), None);
+        assert_eq!(Cursor::decode("nzz"), None);
+    }
+
+    #[actix_rt::test]
+    async fn query_filters() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+
+        let query = FeedbackQuery{
+            category: Some("comentario".to_string()),
+            ..Default::default()
+        };
+        let page = query.fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 2);
+        assert!(page.items.iter().all(|item| item.category == "comentario"));
+
+        let query = FeedbackQuery{
+            username: Some("@ana".to_string()),
+            reference: Some("123".to_string()),
+            ..Default::default()
+        };
+        let page = query.fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, items[2].id);
+
+        items[1].transition(&pool, Status::Answered, "test").await.unwrap();
+        let query = FeedbackQuery{
+            status: Some(Status::Answered),
+            ..Default::default()
+        };
+        let page = query.fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, items[1].id);
+
+        let query = FeedbackQuery{
+            created_from: Some(items[4].created_at),
+            ..Default::default()
+        };
+        let page = query.fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn query_sorts_and_paginates() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+        let ids: Vec<i64> = items.iter().map(|item| item.id).collect();
+
+        let query = FeedbackQuery{
+            sort: Some(SortField::Id),
+            direction: Some(Direction::Asc),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = query.fetch(&pool, None).await.unwrap();
+        assert_eq!(first.items.iter().map(|item| item.id).collect::<Vec<i64>>(), ids[0..2]);
+        assert!(first.prev.is_none());
+
+        let cursor = Cursor::decode(&first.next.unwrap()).unwrap();
+        let second = query.fetch(&pool, Some(&cursor)).await.unwrap();
+        assert_eq!(second.items.iter().map(|item| item.id).collect::<Vec<i64>>(), ids[2..4]);
+
+        let cursor = Cursor::decode(&second.next.unwrap()).unwrap();
+        let third = query.fetch(&pool, Some(&cursor)).await.unwrap();
+        assert_eq!(third.items.iter().map(|item| item.id).collect::<Vec<i64>>(), ids[4..]);
+        assert!(third.next.is_none());
+
+        let cursor = Cursor::decode(&third.prev.unwrap()).unwrap();
+        let back = query.fetch(&pool, Some(&cursor)).await.unwrap();
+        assert_eq!(back.items.iter().map(|item| item.id).collect::<Vec<i64>>(), ids[2..4]);
+        assert!(back.next.is_some());
+
+        let query = FeedbackQuery{
+            sort: Some(SortField::Category),
+            direction: Some(Direction::Desc),
+            limit: Some(3),
+            ..Default::default()
+        };
+        let first = query.fetch(&pool, None).await.unwrap();
+        let categories: Vec<&str> = first.items.iter().map(|item| item.category.as_str()).collect();
+        assert_eq!(categories, ["pregunta", "idea", "idea"]);
+        assert_eq!(first.items[1].id, ids[3]);
+        let cursor = Cursor::decode(&first.next.unwrap()).unwrap();
+        let second = query.fetch(&pool, Some(&cursor)).await.unwrap();
+        assert_eq!(second.items.iter().map(|item| item.id).collect::<Vec<i64>>(), [ids[4], ids[2]]);
+    }
+
+    #[actix_rt::test]
+    async fn soft_delete_restore_and_purge() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+        items[0].transition(&pool, Status::Triaged, "test").await.unwrap();
+
+        assert!(items[0].delete(&pool).await.unwrap());
+        assert!(!items[0].delete(&pool).await.unwrap());
+        assert!(Feedback::read(&pool, items[0].id).await.is_err());
+        let page = FeedbackQuery::default().fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 4);
+        assert_eq!(Feedback::search(&pool, "contenido", 10).await.unwrap().len(), 4);
+        let trash = FeedbackQuery{
+            deleted: Some(true),
+            ..Default::default()
+        };
+        let page = trash.fetch(&pool, None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert!(page.items[0].deleted_at.is_some());
+
+        let restored = Feedback::restore(&pool, items[0].id).await.unwrap();
+        assert!(restored.deleted_at.is_none());
+        assert!(Feedback::restore(&pool, items[0].id).await.is_err());
+
+        items[0].delete(&pool).await.unwrap();
+        assert_eq!(Feedback::purge(&pool, Duration::days(1)).await.unwrap(), 0);
+        assert_eq!(Feedback::purge(&pool, Duration::seconds(-1)).await.unwrap(), 1);
+        assert!(Feedback::restore(&pool, items[0].id).await.is_err());
+        assert!(items[0].transitions(&pool).await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn purge_removes_attachment_files() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+        let directory = std::env::temp_dir().join(format!("purge-{}", std::process::id()));
+        let file = FileRef{
+            kind: "photo".to_string(),
+            file_id: "photo".to_string(),
+            file_unique_id: "photo".to_string(),
+            file_name: None,
+            mime_type: Some("image/jpeg".to_string()),
+            file_size: None,
+            duration: None,
+        };
+        let mut paths = Vec::new();
+        for feedback in &items[..2]{
+            let folder = directory.join(feedback.id.to_string());
+            std::fs::create_dir_all(&folder).unwrap();
+            let path = folder.join("photo.jpg");
+            std::fs::write(&path, b"jpg").unwrap();
+            Attachment::create(&pool, feedback.id, &file, &path.to_string_lossy(), 3).await.unwrap();
+            paths.push(path);
+        }
+
+        items[0].delete(&pool).await.unwrap();
+        assert_eq!(Feedback::purge(&pool, Duration::seconds(-1)).await.unwrap(), 1);
+        assert!(!paths[0].exists());
+        assert!(!paths[0].parent().unwrap().exists());
+        assert!(paths[1].exists());
+        assert_eq!(Attachment::read_all(&pool, items[0].id).await.unwrap().len(), 0);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn edits_keep_history() {
+        let pool = memory_pool().await;
+        let origin = Origin{
+            chat_id: -100123,
+            user_id: 1,
+            message_id: 7,
+            message_thread_id: None,
+        };
+        let mut feedback = Feedback::new_from(&pool, &FeedbackData::new("comentario", "12",
+            "Me gusta el capitulo", "Lorenzo", "atareao", "Telegram"), Some(&origin)).await.unwrap();
+        feedback.set_post_id(&pool, "post1").await.unwrap();
+        let question = Feedback::new_from(&pool, &FeedbackData::new("pregunta", "12",
+            "Me gusta el capitulo", "Lorenzo", "atareao", "Telegram"), Some(&origin)).await.unwrap();
+        assert!(Feedback::read_by_origin(&pool, -100123, 8).await.unwrap().is_empty());
+        let mut found = Feedback::read_by_origin(&pool, -100123, 7).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].id, question.id);
+        let found = found.remove(0);
+        assert_eq!(found.id, feedback.id);
+        assert_eq!(found.mattermost_post_id.as_deref(), Some("post1"));
+
+        let edited = found.edit(&pool, "13", "Me gusta el capítulo").await.unwrap();
+        assert_eq!(edited.content, "Me gusta el capítulo");
+        assert_eq!(edited.reference, "13");
+        let edited = edited.edit(&pool, "13", "Me encanta el capítulo").await.unwrap();
+        let edits = edited.edits(&pool).await.unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0].content, "Me gusta el capitulo");
+        assert_eq!(edits[0].reference, "12");
+        assert_eq!(edits[1].content, "Me gusta el capítulo");
+        assert_eq!(Feedback::search(&pool, "encanta", 10).await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn plans_in_one_step() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+
+        let planned = items[0].plan(&pool, "42", "lorenzo").await.unwrap();
+        assert_eq!(planned.status, Status::Planned);
+        assert_eq!(planned.reference, "42");
+        assert!(planned.edits(&pool).await.unwrap().is_empty());
+        let statuses: Vec<Status> = planned.transitions(&pool).await.unwrap().iter()
+            .map(|transition| transition.to_status)
+            .collect();
+        assert_eq!(statuses, [Status::Triaged, Status::Planned]);
+
+        let answered = items[1].transition(&pool, Status::Answered, "test").await.unwrap();
+        assert!(answered.plan(&pool, "43", "lorenzo").await.is_err());
+        let unchanged = Feedback::read(&pool, answered.id).await.unwrap();
+        assert_eq!(unchanged.status, Status::Answered);
+        assert_eq!(unchanged.reference, answered.reference);
+    }
+
+    #[actix_rt::test]
+    async fn marks_duplicates_in_one_step() {
+        let pool = memory_pool().await;
+        let items = seed(&pool).await;
+
+        assert!(items[0].mark_duplicate(&pool, items[2].id + 100, "lorenzo").await.is_err());
+        let unchanged = Feedback::read(&pool, items[0].id).await.unwrap();
+        assert_eq!(unchanged.status, Status::New);
+        assert!(unchanged.transitions(&pool).await.unwrap().is_empty());
+
+        let duplicate = items[0].mark_duplicate(&pool, items[1].id, "lorenzo").await.unwrap();
+        assert_eq!(duplicate.status, Status::Duplicate);
+        assert_eq!(duplicate.duplicate_of, Some(items[1].id));
+    }
+
+    #[actix_rt::test]
+    async fn follow_ups_share_the_thread() {
+        let pool = memory_pool().await;
+        let origin = Origin{
+            chat_id: -100123,
+            user_id: 1,
+            message_id: 7,
+            message_thread_id: None,
+        };
+        let mut root = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "Un podcast de Rust",
+            "Lorenzo", "atareao", "Telegram"), Some(&origin)).await.unwrap();
+        root.set_bot_message(&pool, 8).await.unwrap();
+        let by_message = Feedback::read_by_reply(&pool, -100123, 7).await.unwrap().unwrap();
+        let by_answer = Feedback::read_by_reply(&pool, -100123, 8).await.unwrap().unwrap();
+        assert_eq!(by_message.id, root.id);
+        assert_eq!(by_answer.id, root.id);
+        assert_eq!(Feedback::read_by_bot_message(&pool, -100123, 8).await.unwrap().unwrap().id, root.id);
+        assert!(Feedback::read_by_bot_message(&pool, -100123, 7).await.unwrap().is_none());
+        assert!(Feedback::read_by_reply(&pool, -100124, 8).await.unwrap().is_none());
+
+        let follow_origin = Origin{message_id: 9, ..origin};
+        let mut follow_up = Feedback::new_from(&pool, &FeedbackData::new("idea", "", "Con ejemplos",
+            "Lorenzo", "atareao", "Telegram"), Some(&follow_origin)).await.unwrap();
+        follow_up.set_parent(&pool, root.id).await.unwrap();
+        assert_eq!(root.thread_root(&pool).await.unwrap().id, root.id);
+        let found = Feedback::read_by_reply(&pool, -100123, 9).await.unwrap().unwrap();
+        assert_eq!(found.thread_root(&pool).await.unwrap().id, root.id);
+
+        root.set_post_id(&pool, "post1").await.unwrap();
+        assert_eq!(Feedback::read_by_post_id(&pool, "post1").await.unwrap().unwrap().id, root.id);
+        assert!(Feedback::read_by_post_id(&pool, "post2").await.unwrap().is_none());
+    }
 }
@@ -4,38 +4,91 @@ mod routes;
 mod telegram;
 mod mattermost;
 mod zinc;
+mod update;
+mod bot;
+mod draft;
+mod polling;
+mod auth;
+mod cli;
+mod config;
+mod channel;
+mod attachment;
+mod playlist;
+mod slash;
+mod actions;
+mod vote;
+#[cfg(test)]
+mod test_utils;
 
 use dotenv::dotenv;
-use std::{env, collections::HashMap};
+use std::{env, process};
 use std::path::Path;
-use sqlx::{sqlite::SqlitePoolOptions, migrate::{Migrator, MigrateDatabase}};
+use sqlx::{sqlite::{SqlitePool, SqlitePoolOptions}, migrate::{Migrator, MigrateDatabase}};
 use actix_web::{App, HttpServer, web::Data, middleware::Logger};
-use routes::{root, status, hook, get_all_feedback, read_one_feedback, create_feedback, update_feedback};
+use routes::{root, status, hook, get_all_feedback, read_one_feedback, create_feedback, update_feedback,
+             transition_feedback, read_feedback_transitions, read_feedback_edits,
+             read_feedback_attachments, download_attachment,
+             read_playlist, export_playlist, mattermost_hook,
+             mattermost_command, mattermost_action, search_feedback,
+             delete_one_feedback, restore_feedback};
 use mattermost::Mattermost;
+use actions::Actions;
 use zinc::Zinc;
+use config::{Config, Mode, Categories};
+use telegram::Telegram;
+use update::User;
+use regex::Regex;
 use env_logger::Env;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
 
 #[derive(Debug, Clone)]
-pub struct Channels{
-    idea: String,
-    comentario: String,
-    pregunta: String,
-    mencion: String,
+pub struct BotInfo{
+    user: User,
+    mention_channel_id: Option<String>,
+    episode_pattern: Regex,
+    attachments_dir: String,
+    actions: Option<Actions>,
+    upvote_emoji: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BotContext{
+    pool: Data<SqlitePool>,
+    categories: Categories,
+    bot_info: BotInfo,
+    telegram: Telegram,
+    mattermost: Mattermost,
+    zinc: Zinc,
+    draft_timeout: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook{
+    url: Option<String>,
+    secret: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MattermostHooks{
+    webhook_tokens: Vec<String>,
+    command_token: Option<String>,
+    user_id: Option<String>,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not set");
-    let port = env::var("PORT").expect("PORT not set");
-    let mattermost_base_uri = env::var("MATTERMOST_BASE_URI").expect("Not found Mattermost Base Uri");
-    let mattermost_token = env::var("MATTERMOST_ACCESS_TOKEN").expect("Not found Mattermost token");
-    let mattermost = Mattermost::new(&mattermost_base_uri, &mattermost_token);
-    let zinc_base_url = env::var("ZINC_BASE_URL").expect("Not found zinc base url");
-    let zinc_indice = env::var("ZINC_INDICE").expect("Not found zinc indice");
-    let zinc_token = env::var("ZINC_TOKEN").expect("Not found token");
-    let zinc = Zinc::new(&zinc_base_url, &zinc_indice, &zinc_token);
+    let config_path = env::var("CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+    let mut config = Config::load(&config_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let db_url = config.database.url.clone();
+    let mattermost = Mattermost::new(&config.mattermost.base_uri, &config.mattermost.token,
+                                     &config.mattermost.team);
+    let zinc = Zinc::new(&config.zinc.base_url, &config.zinc.indice, &config.zinc.token);
+    let telegram = Telegram::new(&config.telegram.token);
 
     if !sqlx::Sqlite::database_exists(&db_url).await.unwrap(){
         sqlx::Sqlite::create_database(&db_url).await.unwrap()
@@ -64,33 +117,144 @@ async fn main() -> std::io::Result<()> {
         .run(&pool)
         .await.unwrap();
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty(){
+        cli::run(Data::new(pool), &config, &args).await;
+        return Ok(());
+    }
+
+    for category in config.categories.iter_mut(){
+        category.channel_id = match mattermost.resolve_channel(&category.channel,
+                config.mattermost.create_channels).await{
+            Ok(channel_id) => channel_id,
+            Err(e) => {
+                eprintln!("Mattermost channel for category {}: {}", category.name, e);
+                process::exit(1);
+            },
+        };
+    }
+    let categories = config.categories.clone();
+    let bot_info = BotInfo{
+        user: telegram.get_me().await.unwrap_or_else(|e| {
+            eprintln!("Can not get the bot user from Telegram: {}", e);
+            process::exit(1);
+        }),
+        mention_channel_id: match &config.mattermost.mention_channel{
+            Some(channel) => match mattermost.resolve_channel(channel,
+                    config.mattermost.create_channels).await{
+                Ok(channel_id) => Some(channel_id),
+                Err(e) => {
+                    eprintln!("Mattermost channel for mentions: {}", e);
+                    process::exit(1);
+                },
+            },
+            None => None,
+        },
+        episode_pattern: Regex::new(&config.telegram.episode_pattern)
+            .expect("episode_pattern already validated"),
+        attachments_dir: config.attachments.directory.clone(),
+        actions: config.mattermost.actions_url.as_ref().map(|url| Actions::new(url,
+            config.mattermost.actions_secret.as_deref().unwrap_or_default())),
+        upvote_emoji: config.telegram.upvote_emoji.clone(),
+    };
 
-    let channels = Channels{
-        idea: mattermost.get_channel_by_name("atareao_idea").await.unwrap(),
-        pregunta: mattermost.get_channel_by_name("atareao_pregunta").await.unwrap(),
-        comentario: mattermost.get_channel_by_name("atareao_comentario").await.unwrap(),
-        mencion: mattermost.get_channel_by_name("atareao_mencion").await.unwrap(),
+    let hooks = MattermostHooks{
+        webhook_tokens: config.mattermost.webhook_tokens.clone(),
+        command_token: config.mattermost.command_token.clone(),
+        user_id: if config.mattermost.webhook_tokens.is_empty(){
+            None
+        }else{
+            match mattermost.get_me().await{
+                Ok(me) => me["id"].as_str().map(|id| id.to_string()),
+                Err(e) => {
+                    eprintln!("Can not get the bot user from Mattermost: {}", e);
+                    process::exit(1);
+                },
+            }
+        },
     };
 
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
+    let context = Data::new(BotContext{
+        pool: Data::new(pool.clone()),
+        categories: categories.clone(),
+        bot_info: bot_info.clone(),
+        telegram: telegram.clone(),
+        mattermost: mattermost.clone(),
+        zinc: zinc.clone(),
+        draft_timeout: config.telegram.draft_timeout,
+    });
+    actix_web::rt::spawn(bot::expire_drafts(Data::new(pool.clone()),
+        telegram.clone(), context.draft_timeout));
+    if config.mattermost.reactions_interval > 0{
+        actix_web::rt::spawn(bot::sync_reactions(Data::new(pool.clone()),
+            mattermost.clone(), config.mattermost.upvote_emoji.clone(),
+            config.mattermost.reactions_interval));
+    }
+
+    let webhook = match config.telegram.mode{
+        Mode::Webhook => {
+            let url = config.telegram.webhook_url.clone().unwrap_or_default();
+            let secret = config.telegram.webhook_secret.clone().unwrap_or_else(|| {
+                thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(64)
+                    .map(char::from)
+                    .collect()
+            });
+            telegram.set_webhook(&url, &secret).await.unwrap_or_else(|e| {
+                eprintln!("Can not set the Telegram webhook: {}", e);
+                process::exit(1);
+            });
+            Webhook{
+                url: Some(url),
+                secret: Some(secret),
+            }
+        },
+        Mode::Polling => {
+            actix_web::rt::spawn(polling::run(context.clone(), config.telegram.polling_timeout));
+            Webhook{
+                url: None,
+                secret: None,
+            }
+        },
+    };
+
     HttpServer::new(move ||{
         App::new()
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
             .app_data(Data::new(pool.clone()))
             .app_data(Data::new(mattermost.clone()))
-            .app_data(Data::new(channels.clone()))
-            .app_data(Data::new(zinc.clone()))
+            .app_data(Data::new(categories.clone()))
+            .app_data(Data::new(hooks.clone()))
+            .app_data(Data::new(bot_info.clone()))
+            .app_data(Data::new(telegram.clone()))
+            .app_data(Data::new(webhook.clone()))
+            .app_data(context.clone())
             .service(root)
             .service(status)
             .service(get_all_feedback)
+            .service(search_feedback)
             .service(read_one_feedback)
             .service(create_feedback)
             .service(update_feedback)
+            .service(delete_one_feedback)
+            .service(restore_feedback)
+            .service(transition_feedback)
+            .service(read_feedback_transitions)
+            .service(read_feedback_edits)
+            .service(read_feedback_attachments)
+            .service(download_attachment)
+            .service(read_playlist)
+            .service(export_playlist)
+            .service(mattermost_hook)
+            .service(mattermost_command)
+            .service(mattermost_action)
             .service(hook)
     })
-        .bind(format!("0.0.0.0:{}", &port))
+        .bind(format!("0.0.0.0:{}", config.server.port))
         .unwrap()
         .run()
         .await
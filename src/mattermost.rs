@@ -1,94 +1,205 @@
 use serde_json::{json, Value};
-use reqwest::{Client, Response};
-use reqwest::header::{HeaderMap, HeaderValue, HeaderName};
-use reqwest::Error;
-use std::str::FromStr;
+use reqwest::{Client, Method, RequestBuilder, StatusCode, multipart::{Form, Part}};
+use std::fmt;
+
+const PER_PAGE: usize = 200;
+
+#[derive(Debug)]
+pub enum MattermostError{
+    Http(reqwest::Error),
+    Api{status: u16, message: String},
+    ChannelNotFound{name: String, available: Vec<String>},
+}
+
+impl fmt::Display for MattermostError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            MattermostError::Http(e) => write!(f, "{}", e),
+            MattermostError::Api{status, message} => write!(f, "Mattermost answered {}: {}", status, message),
+            MattermostError::ChannelNotFound{name, available} if available.is_empty() =>
+                write!(f, "channel {} not found", name),
+            MattermostError::ChannelNotFound{name, available} =>
+                write!(f, "channel {} not found, available channels are {}", name, available.join(", ")),
+        }
+    }
+}
+
+impl From<reqwest::Error> for MattermostError{
+    fn from(e: reqwest::Error) -> Self{
+        MattermostError::Http(e)
+    }
+}
+
+impl MattermostError{
+    fn from_response(status: StatusCode, body: &str) -> MattermostError{
+        let message = serde_json::from_str::<Value>(body).ok()
+            .and_then(|value| value["message"].as_str().map(|message| message.to_string()))
+            .unwrap_or_else(|| body.trim().to_string());
+        MattermostError::Api{status: status.as_u16(), message}
+    }
+
+    fn is_not_found(&self) -> bool{
+        matches!(self, MattermostError::Api{status: 404, ..})
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Mattermost{
     base_uri: String,
     token: String,
+    team: String,
+    client: Client,
 }
 
 impl Mattermost{
-    pub fn new(base_uri: &str, token: &str) -> Mattermost{
+    pub fn new(base_uri: &str, token: &str, team: &str) -> Mattermost{
         Self {
-            base_uri: base_uri.to_string(),
+            base_uri: base_uri.trim_end_matches('/').to_string(),
             token: token.to_string(),
+            team: team.to_string(),
+            client: Client::new(),
         }
     }
 
-    pub async fn post_message(&self, channel_id: &str, message: &str, root_id: Option<&str>) -> Result<Response, Error>{
-        let url = format!("{}/api/v4/posts", self.base_uri);
-        let body = if let Some(value) = root_id{
-            json!({
-                "channel_id": channel_id,
-                "message": message,
-                "root_id": value
-            })
-        }else{
-            json!({
-                "channel_id": channel_id,
-                "message": message,
-            })
-        };
-        self.post(&url, Some(body)).await
-    }
-
-    async fn post(&self, url: &str, body: Option<Value>)->Result<Response, Error>{
-        println!("URL: {}", url);
-        let mut header_map = HeaderMap::new();
-        header_map.insert(HeaderName::from_str("Content-type").unwrap(),
-                          HeaderValue::from_str("application/json").unwrap());
-        header_map.insert(HeaderName::from_str("Authorization").unwrap(),
-                          HeaderValue::from_str(&format!("Bearer {}", self.token)).unwrap());
-        let client = Client::builder()
-            .default_headers(header_map)
-            .build()
-            .unwrap();
-        match body{
-            Some(value) => {
-                let content = serde_json::to_string(&value).unwrap();
-                let res = client.post(url).body(content).send().await?;
-                Ok(res)
-            },
-            None => {
-                let res = client.post(url).send().await?;
-                Ok(res)
-            },
+    fn request(&self, method: Method, path: &str) -> RequestBuilder{
+        let url = format!("{}/api/v4/{}", self.base_uri, path);
+        self.client.request(method, url).bearer_auth(&self.token)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Value, MattermostError>{
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success(){
+            return Err(MattermostError::from_response(status, &body));
         }
+        Ok(serde_json::from_str(&body).unwrap_or_default())
     }
 
-    pub async fn get_channel_by_name(&self, name: &str)-> Option<String>{
-        let channels = self.list_channels().await.unwrap();
-        for channel in channels{
-            let channel_name = channel.get("name").unwrap().as_str().unwrap();
-            let channel_id = channel.get("id").unwrap().as_str().unwrap();
-            if channel_name == name{
-                return Some(channel_id.to_string());
-            }
+    pub async fn post_message(&self, channel_id: &str, message: &str,
+            root_id: Option<&str>, file_ids: &[String], props: Option<&Value>) -> Result<Value, MattermostError>{
+        let mut body = json!({
+            "channel_id": channel_id,
+            "message": message,
+        });
+        if let Some(value) = root_id{
+            body["root_id"] = json!(value);
+        }
+        if !file_ids.is_empty(){
+            body["file_ids"] = json!(file_ids);
+        }
+        if let Some(value) = props{
+            body["props"] = value.clone();
+        }
+        self.send(self.request(Method::POST, "posts").json(&body)).await
+    }
+
+    pub async fn upload_file(&self, channel_id: &str, file_name: &str,
+            content: Vec<u8>) -> Result<Option<String>, MattermostError>{
+        let form = Form::new()
+            .text("channel_id", channel_id.to_string())
+            .part("files", Part::bytes(content).file_name(file_name.to_string()));
+        let response = self.send(self.request(Method::POST, "files").multipart(form)).await?;
+        Ok(response["file_infos"][0]["id"].as_str().map(|id| id.to_string()))
+    }
+
+    pub async fn update_post(&self, post_id: &str, message: &str) -> Result<Value, MattermostError>{
+        let body = json!({
+            "message": message,
+        });
+        self.send(self.request(Method::PUT, &format!("posts/{}/patch", post_id)).json(&body)).await
+    }
+
+    pub async fn get_post(&self, post_id: &str) -> Result<Value, MattermostError>{
+        self.send(self.request(Method::GET, &format!("posts/{}", post_id))).await
+    }
+
+    pub async fn get_reactions(&self, post_id: &str) -> Result<Vec<Value>, MattermostError>{
+        let reactions = self.send(self.request(Method::GET, &format!("posts/{}/reactions", post_id))).await?;
+        Ok(serde_json::from_value(reactions).unwrap_or_default())
+    }
+
+    pub async fn get_me(&self) -> Result<Value, MattermostError>{
+        self.get_user("me").await
+    }
+
+    pub async fn get_user(&self, user_id: &str) -> Result<Value, MattermostError>{
+        self.send(self.request(Method::GET, &format!("users/{}", user_id))).await
+    }
+
+    pub async fn get_team_id(&self) -> Result<String, MattermostError>{
+        let team = self.send(self.request(Method::GET, &format!("teams/name/{}", self.team))).await?;
+        Ok(team["id"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub async fn get_channel_by_name(&self, name: &str) -> Result<Option<String>, MattermostError>{
+        let path = format!("teams/name/{}/channels/name/{}", self.team, name);
+        match self.send(self.request(Method::GET, &path)).await{
+            Ok(channel) => Ok(channel["id"].as_str().map(|id| id.to_string())),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
         }
-        None
     }
 
-    pub async fn list_channels(&self) ->Result<Vec<Value>, Error>{
-        let url = format!("{}/api/v4/channels", self.base_uri);
-        let content = self.get(&url).await?;
-        let json: Vec<Value> = serde_json::from_str(&content).unwrap();
-        Ok(json)
+    pub async fn create_channel(&self, name: &str) -> Result<String, MattermostError>{
+        let body = json!({
+            "team_id": self.get_team_id().await?,
+            "name": name,
+            "display_name": name,
+            "type": "O",
+        });
+        let channel = self.send(self.request(Method::POST, "channels").json(&body)).await?;
+        Ok(channel["id"].as_str().unwrap_or_default().to_string())
     }
 
-    async fn get(&self, url: &str)->Result<String, Error>{
-        println!("URL: {}", url);
-        let mut header_map = HeaderMap::new();
-        header_map.insert(HeaderName::from_str("Authorization").unwrap(),
-                          HeaderValue::from_str(&format!("Bearer {}", self.token)).unwrap());
-        let client = Client::builder()
-            .default_headers(header_map)
-            .build()
-            .unwrap();
-        let res = client.get(url).send().await?.text().await?;
-        Ok(res)
+    pub async fn resolve_channel(&self, name: &str, create: bool) -> Result<String, MattermostError>{
+        if let Some(channel_id) = self.get_channel_by_name(name).await?{
+            return Ok(channel_id);
+        }
+        if create{
+            return self.create_channel(name).await;
+        }
+        let available = self.list_channels().await
+            .map(|channels| channels.iter()
+                .filter_map(|channel| channel["name"].as_str().map(|name| name.to_string()))
+                .collect())
+            .unwrap_or_default();
+        Err(MattermostError::ChannelNotFound{name: name.to_string(), available})
     }
 
+    pub async fn list_channels(&self) -> Result<Vec<Value>, MattermostError>{
+        let team_id = self.get_team_id().await?;
+        let mut channels = Vec::new();
+        for page in 0..{
+            let path = format!("teams/{}/channels?page={}&per_page={}", team_id, page, PER_PAGE);
+            let items: Vec<Value> = serde_json::from_value(
+                self.send(self.request(Method::GET, &path)).await?).unwrap_or_default();
+            let last = items.len() < PER_PAGE;
+            channels.extend(items);
+            if last{
+                break;
+            }
+        }
+        Ok(channels)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::mattermost::MattermostError;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn describes_errors() {
+        let error = MattermostError::from_response(StatusCode::NOT_FOUND,
+            r#"{"id": "app.channel.get_by_name.missing.app_error", "message": "Unable to find the existing channel.", "status_code": 404}"#);
+        assert!(error.is_not_found());
+        assert_eq!(error.to_string(), "Mattermost answered 404: Unable to find the existing channel.");
+        let error = MattermostError::from_response(StatusCode::BAD_GATEWAY, "Bad gateway\n");
+        assert!(!error.is_not_found());
+        assert_eq!(error.to_string(), "Mattermost answered 502: Bad gateway");
+        let error = MattermostError::ChannelNotFound{name: "ideas".to_string(),
+            available: vec!["town-square".to_string(), "off-topic".to_string()]};
+        assert_eq!(error.to_string(), "channel ideas not found, available channels are town-square, off-topic");
+    }
 }
@@ -1,82 +1,377 @@
-use serde_json::Value;
-use regex::Regex;
-
-pub fn command(key: &str, message: &mut Value) -> bool{
-    if let Some(text) = message.get_mut("text"){
-        let content = text.as_str().unwrap();
-        if content.starts_with(&format!(r#"/{}"#, key)){
-            return true;
-        }
+use crate::update::{Message, MessageEntity, EntityType, ChatType, User};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hashtag{
+    pub key: String,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tagged{
+    pub hashtags: Vec<Hashtag>,
+    pub content: String,
+}
+
+impl Tagged{
+    pub fn find(&self, key: &str) -> Option<&Hashtag>{
+        self.hashtags.iter().find(|hashtag| hashtag.key.eq_ignore_ascii_case(key))
     }
-    false
 }
-pub fn check_key(key: &str, message: &mut Value) -> Option<String>{
-    if let Some(text) = message.get_mut("text"){
-        let content = text.as_str().unwrap();
-        if content.contains(&format!(r#"#{}"#, key)){
-            return Some(content.trim().to_string());
-        }
+
+fn text_and_entities(message: &Message) -> Option<(&str, &[MessageEntity])>{
+    match (&message.text, &message.caption){
+        (Some(text), _) => Some((text, &message.entities)),
+        (None, Some(caption)) => Some((caption, &message.caption_entities)),
+        (None, None) => None,
     }
-    None
 }
 
-pub fn check_comment(key: &str, message: &mut Value) -> Option<(Option<String>, Option<String>)>{
-    if let Some(text) = message.get_mut("text"){
-        let content = text.as_str().unwrap();
-        if content.contains(&format!(r#"#{}"#, key)){
-            let patron = format!(r#"#{}\s+(\d*)"#, key);
-            let re = Regex::new(&patron).unwrap();
-            return match re.captures(content) {
-                Some(captures) => {
-                    let referencia = captures.get(1).unwrap().as_str().to_string();
-                    Some((Some(referencia), Some(content.to_string())))
-                },
-                None => Some((None, Some(content.to_string()))),
-            };
+pub fn command(key: &str, message: &Message, bot: &User) -> bool{
+    let (text, entities) = match text_and_entities(message){
+        Some(found) => found,
+        None => return false,
+    };
+    let username = bot.username.as_deref().unwrap_or_default();
+    entities.iter()
+        .filter(|entity| entity.kind == EntityType::BotCommand)
+        .filter_map(|entity| entity_text(text, entity))
+        .any(|command| {
+            let mut parts = command.trim_start_matches('/').splitn(2, '@');
+            let name = parts.next().unwrap_or_default();
+            let target = parts.next();
+            name.eq_ignore_ascii_case(key) &&
+                target.map(|target| target.eq_ignore_ascii_case(username)).unwrap_or(true)
+        })
+}
+
+fn is_blank(unit: u16) -> bool{
+    char::from_u32(unit as u32).map(|c| c.is_whitespace()).unwrap_or(false)
+}
+
+fn is_digit(unit: u16) -> bool{
+    (b'0' as u16..=b'9' as u16).contains(&unit)
+}
+
+pub fn hashtags(message: &Message, keys: &[(&str, bool)]) -> Tagged{
+    let (text, entities) = match text_and_entities(message){
+        Some(found) => found,
+        None => return Tagged{hashtags: Vec::new(), content: String::new()},
+    };
+    let utf16: Vec<u16> = text.encode_utf16().collect();
+    let mut found: Vec<Hashtag> = Vec::new();
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for entity in entities.iter().filter(|entity| entity.kind == EntityType::Hashtag){
+        let tag = match entity_text(text, entity){
+            Some(tag) => tag,
+            None => continue,
+        };
+        let tag = tag.trim_start_matches('#');
+        let (key, with_reference) = match keys.iter().find(|(key, _)| key.eq_ignore_ascii_case(tag)){
+            Some(key) => key,
+            None => continue,
+        };
+        let start = entity.offset as usize;
+        let mut end = start + entity.length as usize;
+        let mut reference = None;
+        if *with_reference{
+            let mut digits = end;
+            while digits < utf16.len() && is_blank(utf16[digits]){
+                digits += 1;
+            }
+            let from = digits;
+            while digits < utf16.len() && is_digit(utf16[digits]){
+                digits += 1;
+            }
+            if digits > from{
+                reference = Some(String::from_utf16_lossy(&utf16[from..digits]));
+                end = digits;
+            }
+        }
+        spans.push((start, end));
+        match found.iter_mut().find(|hashtag| hashtag.key == key.to_lowercase()){
+            Some(hashtag) => {
+                if hashtag.reference.is_none(){
+                    hashtag.reference = reference;
+                }
+            },
+            None => found.push(Hashtag{key: key.to_lowercase(), reference}),
         }
-    }else if let Some(text) = message.get_mut("caption"){
-        let content = text.as_str().unwrap();
-        if content.contains(&format!(r#"#{}"#, key)){
-            let patron = format!(r#"#{}\s+(\d*)"#, key);
-            let re = Regex::new(&patron).unwrap();
-            return match re.captures(content) {
-                Some(captures) => {
-                    let referencia = captures.get(1).unwrap().as_str().to_string();
-                    Some((Some(referencia), Some(content.to_string())))
-                },
-                None => Some((None, Some(content.to_string()))),
-            };
+    }
+    let mut kept: Vec<u16> = Vec::with_capacity(utf16.len());
+    for (index, unit) in utf16.iter().enumerate(){
+        if !spans.iter().any(|(start, end)| (*start..*end).contains(&index)){
+            kept.push(*unit);
         }
     }
-    None
+    let content = String::from_utf16_lossy(&kept)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect::<Vec<String>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    Tagged{hashtags: found, content}
 }
 
-pub fn get_user(message: &mut Value) -> (String, String){
-    let mut name: String = "".to_string();
-    let mut nick: String = "".to_string();
-    if let Some(from) = message.get_mut("from"){
-        if let Some(first_name) = from.get_mut("first_name"){
-            name = first_name.as_str().unwrap().to_string();
-        }
-        if let Some(username) = from.get_mut("username"){
-            nick = username.as_str().unwrap().to_string();
-        }
+pub fn get_user(message: &Message) -> (String, String){
+    match &message.from{
+        Some(from) => (
+            from.first_name.clone(),
+            from.username.clone().unwrap_or_default(),
+        ),
+        None => ("".to_string(), "".to_string()),
+    }
+}
+
+pub fn entity_text(text: &str, entity: &MessageEntity) -> Option<String>{
+    let utf16: Vec<u16> = text.encode_utf16().collect();
+    let start = usize::try_from(entity.offset).ok()?;
+    let end = start.checked_add(usize::try_from(entity.length).ok()?)?;
+    utf16.get(start..end).map(String::from_utf16_lossy)
+}
+
+pub fn is_mention(message: &Message, bot: &User) -> bool{
+    let replies_to_bot = message.reply_to_message.as_ref()
+        .and_then(|reply| reply.from.as_ref())
+        .map(|from| from.id == bot.id)
+        .unwrap_or(false);
+    if replies_to_bot{
+        return true;
     }
-    (name, nick)
+    let (text, entities) = match text_and_entities(message){
+        Some(found) => found,
+        None => return false,
+    };
+    let username = bot.username.as_deref().unwrap_or_default();
+    entities.iter().any(|entity| match entity.kind{
+        EntityType::Mention => entity_text(text, entity)
+            .map(|mention| mention.trim_start_matches('@').eq_ignore_ascii_case(username))
+            .unwrap_or(false),
+        EntityType::TextMention => entity.user.as_ref()
+            .map(|user| user.id == bot.id)
+            .unwrap_or(false),
+        _ => false,
+    })
 }
 
-pub fn get_message_thread_id(message: &mut Value) -> Option<i64>{
-    if let Some(message_thread_id) = message.get_mut("message_thread_id"){
-        return Some(message_thread_id.as_i64().unwrap())
+pub fn permalink(message: &Message) -> Option<String>{
+    let thread = match message.message_thread_id{
+        Some(thread) if message.is_topic_message => format!("{}/", thread),
+        _ => String::new(),
+    };
+    match (&message.chat.kind, &message.chat.username){
+        (ChatType::Private, _) => None,
+        (_, Some(username)) => Some(format!("https://t.me/{}/{}{}", username, thread,
+                                            message.message_id)),
+        (ChatType::Supergroup | ChatType::Channel, None) => {
+            let id = message.chat.id.to_string();
+            id.strip_prefix("-100").map(|id| format!("https://t.me/c/{}/{}{}", id, thread,
+                                                     message.message_id))
+        },
+        _ => None,
     }
-    None
 }
 
-pub fn get_chat_id(message: &mut Value) -> Option<i64>{
-    if let Some(chat) = message.get_mut("chat"){
-        if let Some(id) = chat.get_mut("id"){
-            return Some(id.as_i64().unwrap());
+#[cfg(test)]
+mod tests{
+    use crate::message::{entity_text, is_mention, permalink, command, hashtags, Hashtag};
+    use crate::update::{Message, User};
+    use serde_json::json;
+
+    fn bot() -> User{
+        serde_json::from_value(json!({
+            "id": 42, "is_bot": true, "first_name": "Support", "username": "SupportBot"
+        })).unwrap()
+    }
+
+    fn message(value: serde_json::Value) -> Message{
+        let mut base = json!({
+            "message_id": 7,
+            "date": 0,
+            "from": {"id": 1, "is_bot": false, "first_name": "Lorenzo"},
+            "chat": {"id": -1001234, "type": "supergroup", "title": "atareao"},
+        });
+        for (key, item) in value.as_object().unwrap(){
+            base[key] = item.clone();
         }
+        serde_json::from_value(base).unwrap()
+    }
+
+    fn entity(text: &str, needle: &str, kind: &str) -> serde_json::Value{
+        let start = text.find(needle).unwrap();
+        json!({
+            "type": kind,
+            "offset": text[..start].encode_utf16().count(),
+            "length": needle.encode_utf16().count(),
+        })
+    }
+
+    fn tagged(text: &str, tags: &[&str]) -> Message{
+        let entities: Vec<serde_json::Value> = tags.iter()
+            .map(|tag| entity(text, tag, "hashtag"))
+            .collect();
+        message(json!({"text": text, "entities": entities}))
+    }
+
+    const KEYS: [(&str, bool); 3] = [("idea", false), ("pregunta", false), ("comentario", true)];
+
+    fn hashtag(key: &str, reference: Option<&str>) -> Hashtag{
+        Hashtag{key: key.to_string(), reference: reference.map(|r| r.to_string())}
+    }
+
+    #[test]
+    fn strips_the_hashtag() {
+        let result = hashtags(&tagged("#idea un capítulo sobre Rust", &["#idea"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("idea", None)]);
+        assert_eq!(result.content, "un capítulo sobre Rust");
+
+        let result = hashtags(&tagged("¿Cuanto duermes? #pregunta", &["#pregunta"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("pregunta", None)]);
+        assert_eq!(result.content, "¿Cuanto duermes?");
+
+        let result = hashtags(&tagged("un #idea en medio", &["#idea"]), &KEYS);
+        assert_eq!(result.content, "un en medio");
+    }
+
+    #[test]
+    fn matches_case_insensitively() {
+        let result = hashtags(&tagged("#IDEA algo", &["#IDEA"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("idea", None)]);
+        assert_eq!(result.content, "algo");
+        assert!(result.find("Idea").is_some());
+    }
+
+    #[test]
+    fn ignores_similar_hashtags_and_plain_text() {
+        let result = hashtags(&tagged("#ideas y #ideal", &["#ideas", "#ideal"]), &KEYS);
+        assert!(result.hashtags.is_empty());
+        assert_eq!(result.content, "#ideas y #ideal");
+
+        let result = hashtags(&message(json!({
+            "text": "mira https://atareao.es/#idea",
+            "entities": [entity("mira https://atareao.es/#idea", "https://atareao.es/#idea", "url")],
+        })), &KEYS);
+        assert!(result.hashtags.is_empty());
+
+        let result = hashtags(&message(json!({"text": "sin entidades #idea"})), &KEYS);
+        assert!(result.hashtags.is_empty());
+    }
+
+    #[test]
+    fn keeps_unrelated_hashtags() {
+        let result = hashtags(&tagged("#idea hablar de #rust", &["#idea", "#rust"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("idea", None)]);
+        assert_eq!(result.content, "hablar de #rust");
+    }
+
+    #[test]
+    fn supports_multiple_hashtags() {
+        let text = "#idea #pregunta ¿harás un capítulo de Rust? #idea";
+        let message = message(json!({"text": text, "entities": [
+            {"type": "hashtag", "offset": 0, "length": 5},
+            {"type": "hashtag", "offset": 6, "length": 9},
+            {"type": "hashtag", "offset": 44, "length": 5},
+        ]}));
+        let result = hashtags(&message, &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("idea", None), hashtag("pregunta", None)]);
+        assert_eq!(result.content, "¿harás un capítulo de Rust?");
+    }
+
+    #[test]
+    fn parses_the_reference() {
+        let result = hashtags(&tagged("#comentario 123 me gusta", &["#comentario"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("comentario", Some("123"))]);
+        assert_eq!(result.content, "me gusta");
+
+        let result = hashtags(&tagged("me gusta #comentario", &["#comentario"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("comentario", None)]);
+        assert_eq!(result.content, "me gusta");
+
+        let result = hashtags(&tagged("#idea 123 cosas", &["#idea"]), &KEYS);
+        assert_eq!(result.content, "123 cosas");
+    }
+
+    #[test]
+    fn uses_utf16_offsets() {
+        let text = "🎧🎧 me encanta 👏 #comentario 45 👏";
+        let result = hashtags(&tagged(text, &["#comentario"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("comentario", Some("45"))]);
+        assert_eq!(result.content, "🎧🎧 me encanta 👏 👏");
+    }
+
+    #[test]
+    fn reads_captions_and_keeps_lines() {
+        let caption = "#pregunta\nprimera línea\n\nsegunda   línea";
+        let result = hashtags(&message(json!({
+            "caption": caption,
+            "caption_entities": [entity(caption, "#pregunta", "hashtag")],
+        })), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("pregunta", None)]);
+        assert_eq!(result.content, "primera línea\n\nsegunda línea");
+    }
+
+    #[test]
+    fn empty_content_after_stripping() {
+        let result = hashtags(&tagged("#idea", &["#idea"]), &KEYS);
+        assert_eq!(result.hashtags, vec![hashtag("idea", None)]);
+        assert!(result.content.is_empty());
+    }
+
+    #[test]
+    fn detects_commands() {
+        let bot = bot();
+        let command_message = |text: &str| {
+            let entities = vec![entity(text, text.split_whitespace().next().unwrap(), "bot_command")];
+            message(json!({"text": text, "entities": entities}))
+        };
+        assert!(command("ayuda", &command_message("/ayuda"), &bot));
+        assert!(command("ayuda", &command_message("/ayuda@SupportBot"), &bot));
+        assert!(command("ayuda", &command_message("/Ayuda@supportbot por favor"), &bot));
+        assert!(!command("ayuda", &command_message("/ayuda@OtroBot"), &bot));
+        assert!(!command("ayuda", &command_message("/ayudame"), &bot));
+        assert!(!command("ayuda", &message(json!({"text": "/ayuda"})), &bot));
+    }
+
+    #[test]
+    fn slices_entities_in_utf16() {
+        let message = message(json!({
+            "text": "🎧 hola @supportbot",
+            "entities": [{"type": "mention", "offset": 8, "length": 11}],
+        }));
+        assert_eq!(entity_text(message.text.as_ref().unwrap(), &message.entities[0]).unwrap(),
+                   "@supportbot");
+        assert!(is_mention(&message, &bot()));
+    }
+
+    #[test]
+    fn detects_mentions() {
+        let other = message(json!({
+            "text": "hola @otro",
+            "entities": [{"type": "mention", "offset": 5, "length": 5}],
+        }));
+        assert!(!is_mention(&other, &bot()));
+        let text_mention = message(json!({
+            "text": "hola Support",
+            "entities": [{"type": "text_mention", "offset": 5, "length": 7, "user": bot()}],
+        }));
+        assert!(is_mention(&text_mention, &bot()));
+        let reply = message(json!({
+            "text": "gracias",
+            "reply_to_message": {"message_id": 6, "date": 0, "from": bot(),
+                                 "chat": {"id": -1001234, "type": "supergroup"}},
+        }));
+        assert!(is_mention(&reply, &bot()));
+    }
+
+    #[test]
+    fn builds_permalinks() {
+        assert_eq!(permalink(&message(json!({}))).unwrap(), "https://t.me/c/1234/7");
+        let public = message(json!({
+            "chat": {"id": -1005678, "type": "supergroup", "username": "atareao_con_linux"},
+            "message_thread_id": 3,
+            "is_topic_message": true,
+        }));
+        assert_eq!(permalink(&public).unwrap(), "https://t.me/atareao_con_linux/3/7");
+        assert!(permalink(&message(json!({"chat": {"id": 1, "type": "private"}}))).is_none());
     }
-    None
 }
@@ -0,0 +1,159 @@
+use actix_web::web;
+use sqlx::{sqlite::SqlitePool, FromRow, Error};
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use std::{fs, io::{Cursor, Write}, path::Path};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::attachment::AttachmentError;
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Track{
+    pub attachment_id: i64,
+    pub feedback_id: i64,
+    pub category: String,
+    pub reference: String,
+    pub content: String,
+    pub username: String,
+    pub nickname: String,
+    pub kind: String,
+    pub mime_type: Option<String>,
+    pub duration: Option<i64>,
+    pub file_size: Option<i64>,
+    #[serde(skip)]
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Track{
+    pub async fn read_all(pool: &web::Data<SqlitePool>, episode: &str,
+            category: Option<&str>) -> Result<Vec<Track>, Error>{
+        sqlx::query_as::<_, Track>("SELECT a.id AS attachment_id, a.feedback_id,
+                    f.category, f.reference, f.content, f.username, f.nickname,
+                    a.kind, a.mime_type, a.duration, a.file_size, a.path,
+                    f.created_at
+                FROM attachments a JOIN feedback f ON f.id = a.feedback_id
+                WHERE f.reference = $1 AND f.deleted_at IS NULL
+                    AND a.kind IN ('voice', 'audio')
+                    AND ($2 IS NULL OR f.category = $2)
+                ORDER BY f.created_at, a.id")
+            .bind(episode)
+            .bind(category)
+            .fetch_all(pool.get_ref())
+            .await
+    }
+
+    pub fn author(&self) -> &str{
+        if self.nickname.is_empty() {&self.username} else {&self.nickname}
+    }
+
+    pub fn file_name(&self, position: usize) -> String{
+        let author: String = self.author()
+            .chars()
+            .map(|c| if c.is_alphanumeric() {c} else {'_'})
+            .collect();
+        let extension = Path::new(&self.path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default();
+        format!("{:03}-{}-{}{}", position, author, self.feedback_id, extension)
+    }
+}
+
+fn csv_field(value: &str) -> String{
+    if value.contains([',', '"', '\n']){
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }else{
+        value.to_string()
+    }
+}
+
+pub fn index(tracks: &[Track]) -> String{
+    let mut start = 0;
+    let mut index = String::from("track,file,start,duration,author,feedback_id,content\n");
+    for (position, track) in tracks.iter().enumerate(){
+        let duration = track.duration.unwrap_or_default();
+        index.push_str(&format!("{},{},{},{},{},{},{}\n", position + 1,
+            csv_field(&track.file_name(position + 1)), start, duration,
+            csv_field(track.author()), track.feedback_id, csv_field(&track.content)));
+        start += duration;
+    }
+    index
+}
+
+pub fn export(tracks: &[Track]) -> Result<Vec<u8>, AttachmentError>{
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("index.csv", options)?;
+    zip.write_all(index(tracks).as_bytes())?;
+    for (position, track) in tracks.iter().enumerate(){
+        zip.start_file(track.file_name(position + 1), options)?;
+        zip.write_all(&fs::read(&track.path)?)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::attachment::{Attachment, FileRef};
+    use crate::feedback::{Feedback, FeedbackData};
+    use crate::playlist::{Track, index, export};
+    use crate::test_utils::memory_pool;
+    use std::io::{Cursor, Read};
+
+    fn voice(id: &str, duration: i64) -> FileRef{
+        FileRef{
+            kind: "voice".to_string(),
+            file_id: id.to_string(),
+            file_unique_id: id.to_string(),
+            file_name: None,
+            mime_type: Some("audio/ogg".to_string()),
+            file_size: None,
+            duration: Some(duration),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn lists_and_exports_episode() {
+        let pool = memory_pool().await;
+        let directory = std::env::temp_dir().join(format!("playlist-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for (name, nick, reference, duration) in [("Juan", "juan", "42", 12), ("Ana", "", "42", 30),
+                                                 ("Luis", "luis", "43", 5)]{
+            let data = FeedbackData::new("pregunta", reference, "Nota de voz, ¿qué tal?", name, nick,
+                "Telegram");
+            let feedback = Feedback::new_from(&pool, &data, None).await.unwrap();
+            let path = directory.join(format!("{}.oga", feedback.id));
+            std::fs::write(&path, name.as_bytes()).unwrap();
+            Attachment::create(&pool, feedback.id, &voice(name, duration),
+                &path.to_string_lossy(), 4).await.unwrap();
+        }
+        let photo = Feedback::new_from(&pool, &FeedbackData::new("idea", "42", "foto",
+            "Eva", "eva", "Telegram"), None).await.unwrap();
+        let mut image = voice("photo", 0);
+        image.kind = "photo".to_string();
+        Attachment::create(&pool, photo.id, &image, "foto.jpg", 4).await.unwrap();
+
+        let tracks = Track::read_all(&pool, "42", Some("pregunta")).await.unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].file_name(1), format!("001-juan-{}.oga", tracks[0].feedback_id));
+        assert_eq!(tracks[1].author(), "Ana");
+        assert_eq!(Track::read_all(&pool, "42", None).await.unwrap().len(), 2);
+
+        let index = index(&tracks);
+        let lines: Vec<&str> = index.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with(&format!("2,002-Ana-{}.oga,12,30,Ana,", tracks[1].feedback_id)));
+        assert!(lines[2].ends_with(",\"Nota de voz, ¿qué tal?\""));
+
+        let bundle = export(&tracks).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bundle)).unwrap();
+        assert_eq!(archive.len(), 3);
+        let mut content = String::new();
+        archive.by_name(&tracks[1].file_name(2)).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "Ana");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
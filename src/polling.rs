@@ -0,0 +1,77 @@
+use actix_web::{web, rt::time::sleep};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row, query, Error};
+use serde_json::json;
+use chrono::Utc;
+use std::time::Duration;
+
+use crate::{
+    bot::process_update,
+    update::Update,
+    BotContext,
+};
+
+pub async fn read_offset(pool: &web::Data<SqlitePool>) -> Result<Option<i64>, Error>{
+    let sql = "SELECT last_update_id FROM polling WHERE id = 1";
+    query(sql)
+        .map(|row: SqliteRow| row.get("last_update_id"))
+        .fetch_optional(pool.get_ref())
+        .await
+}
+
+pub async fn save_offset(pool: &web::Data<SqlitePool>, last_update_id: i64) -> Result<(), Error>{
+    let updated_at = Utc::now().naive_utc();
+    let sql = "INSERT INTO polling (id, last_update_id, updated_at)
+               VALUES (1, $1, $2) ON CONFLICT(id) DO UPDATE SET
+               last_update_id = excluded.last_update_id,
+               updated_at = excluded.updated_at";
+    query(sql)
+        .bind(last_update_id)
+        .bind(updated_at)
+        .execute(pool.get_ref())
+        .await?;
+    Ok(())
+}
+
+pub async fn run(context: web::Data<BotContext>, timeout: u64){
+    let BotContext{pool, telegram, zinc, ..} = context.get_ref();
+    if let Err(e) = telegram.delete_webhook().await{
+        println!("No he podido borrar el webhook: {}", e);
+    }
+    loop{
+        let offset = match read_offset(pool).await{
+            Ok(last_update_id) => last_update_id.map(|id| id + 1),
+            Err(e) => {
+                println!("No he podido leer el offset: {}", e);
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            },
+        };
+        let updates = match telegram.get_updates(offset, timeout).await{
+            Ok(updates) => updates,
+            Err(e) => {
+                println!("No he podido obtener actualizaciones: {}", e);
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            },
+        };
+        for value in updates{
+            if let Err(e) = zinc.publish(&json!([{
+                "src": "Telegram",
+                "type": "polling",
+                "post": value.to_string(),
+            }])).await{
+                println!("No he podido publicar en zinc: {:?}", e);
+            }
+            match serde_json::from_value::<Update>(value.clone()){
+                Ok(update) => process_update(&context, &update).await,
+                Err(e) => println!("Update ignorado, no lo he podido leer: {}", e),
+            }
+            match value["update_id"].as_i64(){
+                Some(update_id) => if let Err(e) = save_offset(pool, update_id).await{
+                    println!("No he podido guardar el offset: {}", e);
+                },
+                None => println!("Update sin update_id: {}", value),
+            }
+        }
+    }
+}
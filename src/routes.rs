@@ -1,28 +1,33 @@
 use actix_web::{get, post, put, delete, web, Error, HttpResponse, http::StatusCode,
-                http::header::ContentType, HttpRequest,
-                error::{ErrorBadRequest, ErrorNotFound}};
-use serde::Serialize;
+                http::header::{ContentType, ContentDisposition, DispositionType,
+                               DispositionParam, ExtendedValue, Charset},
+                HttpRequest,
+                error::ErrorBadRequest};
+use serde::{Serialize, Deserialize};
 use serde_json::{Value, json};
 use sqlx::sqlite::SqlitePool;
-use std::env;
-use reqwest::header::AUTHORIZATION;
+use std::str::FromStr;
 
 use crate::{
-    feedback::Feedback,
-    message::{
-        check_key,
-        get_user,
-        check_comment,
-        command,
-        get_chat_id,
-        get_message_thread_id,
-    },
-    telegram::send_message,
+    auth::{ApiKey, Scope, constant_time_eq},
+    attachment::Attachment,
+    playlist::{self, Track},
+    feedback::{Feedback, FeedbackData, Status, FeedbackQuery, Cursor},
+    bot::{process_update, notify_author, relay_reply},
+    update::Update,
     mattermost::Mattermost,
-    zinc::Zinc,
-    Channels
+    telegram::Telegram,
+    config::Categories,
+    BotContext,
+    BotInfo,
+    Webhook,
+    MattermostHooks,
+    slash::{self, Command},
+    actions::ActionCallback,
 };
 
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
 #[derive(Serialize)]
 struct Respuesta{
     code: i32,
@@ -36,14 +41,13 @@ impl Respuesta {
             status: if code < 300 {"OK".to_string()} else {"KO".to_string()},
             content,
         };
-        match code{
-            0 ..= 299 => Ok(HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&respuesta)?)),
-            _ => Ok(HttpResponse::BadRequest()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&respuesta)?)),
-        }
+        let status_code = match code{
+            0 ..= 299 => StatusCode::OK,
+            _ => StatusCode::from_u16(code as u16).unwrap_or(StatusCode::BAD_REQUEST),
+        };
+        Ok(HttpResponse::build(status_code)
+            .content_type(ContentType::json())
+            .body(serde_json::to_string(&respuesta)?))
     }
 
     fn simple(code: i32, message: &str) -> Result<HttpResponse, Error>{
@@ -53,36 +57,65 @@ impl Respuesta {
 }
 
 #[get("/")]
-pub async fn root(req: HttpRequest) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
+pub async fn root(_api_key: ApiKey) -> Result<HttpResponse, Error>{
     Respuesta::simple(200, "Rust es lo mejor!")
 }
 
+fn page_link(req: &HttpRequest, cursor: &str) -> String{
+    let mut params: Vec<&str> = req.query_string()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("cursor="))
+        .collect();
+    let cursor = format!("cursor={}", cursor);
+    params.push(&cursor);
+    format!("{}?{}", req.path(), params.join("&"))
+}
+
 #[get("/feedback")]
-pub async fn get_all_feedback(req: HttpRequest, pool: web::Data<SqlitePool>) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
-    Feedback::read_all(pool)
+pub async fn get_all_feedback(req: HttpRequest, api_key: ApiKey, pool: web::Data<SqlitePool>,
+        params: web::Query<FeedbackQuery>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let cursor = match &params.cursor{
+        Some(value) => match Cursor::decode(value){
+            Some(cursor) => Some(cursor),
+            None => return Respuesta::simple(400, "Bad request!, invalid cursor"),
+        },
+        None => None,
+    };
+    let page = params.fetch(&pool, cursor.as_ref())
         .await
-        .map(|some_notes| HttpResponse::Ok().json(some_notes))
-        .map_err(|_| ErrorBadRequest("Not found"))
+        .map_err(|_| ErrorBadRequest("Not found"))?;
+    Ok(HttpResponse::Ok().json(json!({
+        "items": page.items,
+        "next": page.next.map(|cursor| page_link(&req, &cursor)),
+        "prev": page.prev.map(|cursor| page_link(&req, &cursor)),
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams{
+    q: String,
+    limit: Option<i64>,
+}
+
+#[get("/feedback/search")]
+pub async fn search_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        params: web::Query<SearchParams>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    if params.q.trim().is_empty(){
+        return Respuesta::simple(400, "Bad request!, q is mandatory");
+    }
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    match Feedback::search(&pool, &params.q, limit).await{
+        Ok(results) => Respuesta::new(200, serde_json::to_value(results).unwrap()),
+        Err(_) => Respuesta::simple(400, "Bad request"),
+    }
 }
 
 #[get("/feedback/{id}")]
-pub async fn read_one_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
+pub async fn read_one_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
         path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
+    api_key.require(Scope::FeedbackRead)?;
     let id = path_id.into_inner();
     match Feedback::read(&pool, id).await{
         Ok(feedback) => Respuesta::new(200,serde_json::to_value(feedback).unwrap()),
@@ -91,108 +124,210 @@ pub async fn read_one_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
 }
 
 #[delete("/feedback/{id}")]
-pub async fn delete_one_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
+pub async fn delete_one_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
         path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
+    api_key.require(Scope::FeedbackWrite)?;
     let id = path_id.into_inner();
     match Feedback::read(&pool, id).await{
-        Ok(feedback) => {
-            feedback.delete(&pool).await.unwrap();
-            Respuesta::new(200,serde_json::to_value(feedback).unwrap())
+        Ok(feedback) => match feedback.delete(&pool).await{
+            Ok(_) => Respuesta::new(200,serde_json::to_value(feedback).unwrap()),
+            Err(_) => Respuesta::simple(400, "Bad request"),
         },
         Err(_) => Respuesta::simple(400, &format!("Feedback {} not found", id)),
     }
 }
 
+#[post("/feedback/{id}/restore")]
+pub async fn restore_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackWrite)?;
+    let id = path_id.into_inner();
+    match Feedback::restore(&pool, id).await{
+        Ok(feedback) => Respuesta::new(200, serde_json::to_value(feedback).unwrap()),
+        Err(_) => Respuesta::simple(400, &format!("Deleted feedback {} not found", id)),
+    }
+}
+
 #[put("/feedback/{id}")]
-pub async fn update_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
+pub async fn update_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
         path_id: web::Path<i64>, post: String) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
+    api_key.require(Scope::FeedbackWrite)?;
     let id = path_id.into_inner();
-    let mut post_content: Value = serde_json::from_str(&post).unwrap();
-    let category = match post_content.get_mut("category") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => return Respuesta::simple(400, "Bad request!, category is mandatory")
+    let data: FeedbackData = match serde_json::from_str(&post){
+        Ok(data) => data,
+        Err(e) => return Respuesta::simple(400, &format!("Bad request!, {}", e)),
     };
-    let reference = match post_content.get_mut("reference") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
-    };
-    let content = match post_content.get_mut("content") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => return Respuesta::simple(400, "Bad request!, content is mandatory")
-    };
-    let username = match post_content.get_mut("username") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
-    };
-    let nickname = match post_content.get_mut("nickname") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
-    };
-    let applied = match post_content.get_mut("applied") {
-        Some(value) => value.as_i64().unwrap(),
-        None => 0,
-    };
-    let source = match post_content.get_mut("source") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
-    };
-
-    match Feedback::update_from(&pool, id, &category, &reference, &content, &username, &nickname, applied, &source)
+    match Feedback::update_from(&pool, id, &data)
         .await{
             Ok(feedback) => Respuesta::new(200, serde_json::to_value(feedback).unwrap()),
             Err(_) => Respuesta::simple(400, "Bad request"),
         }
 }
 
-#[post("/feedback")]
-pub async fn create_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
+#[post("/feedback/{id}/transition")]
+pub async fn transition_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        telegram: web::Data<Telegram>, path_id: web::Path<i64>,
         post: String) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
-    }
-    let mut post_content: Value = serde_json::from_str(&post).unwrap();
-    let category = match post_content.get_mut("category") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => return Respuesta::simple(400, "Bad request!, category is mandatory")
+    api_key.require(Scope::FeedbackWrite)?;
+    let id = path_id.into_inner();
+    let post_content: Value = match serde_json::from_str(&post){
+        Ok(value) => value,
+        Err(_) => return Respuesta::simple(400, "Bad request!, invalid json"),
     };
-    let reference = match post_content.get_mut("reference") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
+    let to = match post_content.get("status").and_then(|value| value.as_str()) {
+        Some(value) => match Status::from_str(value){
+            Ok(value) => value,
+            Err(e) => return Respuesta::simple(400, &e),
+        },
+        None => return Respuesta::simple(400, "Bad request!, status is mandatory")
     };
-    let content = match post_content.get_mut("content") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => return Respuesta::simple(400, "Bad request!, content is mandatory")
+    let actor = match post_content.get("actor").and_then(|value| value.as_str()) {
+        Some(value) => value.to_string(),
+        None => api_key.name.clone(),
     };
-    let username = match post_content.get_mut("username") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
+    let feedback = match Feedback::read(&pool, id).await{
+        Ok(feedback) => feedback,
+        Err(_) => return Respuesta::simple(400, &format!("Feedback {} not found", id)),
     };
-    let nickname = match post_content.get_mut("nickname") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
+    match feedback.transition(&pool, to, &actor).await{
+        Ok(feedback) => {
+            if let Err(e) = notify_author(&telegram, &feedback).await{
+                println!("No he podido avisar al autor de {}: {}", feedback.id, e);
+            }
+            Respuesta::new(200, serde_json::to_value(feedback).unwrap())
+        },
+        Err(e) => Respuesta::simple(400, &e.to_string()),
+    }
+}
+
+#[get("/feedback/{id}/transitions")]
+pub async fn read_feedback_transitions(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let id = path_id.into_inner();
+    let feedback = match Feedback::read(&pool, id).await{
+        Ok(feedback) => feedback,
+        Err(_) => return Respuesta::simple(400, &format!("Feedback {} not found", id)),
     };
-    let applied = match post_content.get_mut("applied") {
-        Some(value) => value.as_i64().unwrap(),
-        None => 0,
+    match feedback.transitions(&pool).await{
+        Ok(transitions) => Respuesta::new(200, serde_json::to_value(transitions).unwrap()),
+        Err(_) => Respuesta::simple(400, "Bad request"),
+    }
+}
+
+#[get("/feedback/{id}/edits")]
+pub async fn read_feedback_edits(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let id = path_id.into_inner();
+    let feedback = match Feedback::read(&pool, id).await{
+        Ok(feedback) => feedback,
+        Err(_) => return Respuesta::simple(400, &format!("Feedback {} not found", id)),
     };
-    let source = match post_content.get_mut("source") {
-        Some(value) => value.as_str().unwrap().to_string(),
-        None => "".to_string(),
+    match feedback.edits(&pool).await{
+        Ok(edits) => Respuesta::new(200, serde_json::to_value(edits).unwrap()),
+        Err(_) => Respuesta::simple(400, "Bad request"),
+    }
+}
+
+#[get("/feedback/{id}/attachments")]
+pub async fn read_feedback_attachments(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path_id: web::Path<i64>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let id = path_id.into_inner();
+    if Feedback::read(&pool, id).await.is_err(){
+        return Respuesta::simple(400, &format!("Feedback {} not found", id));
+    }
+    match Attachment::read_all(&pool, id).await{
+        Ok(attachments) => Respuesta::new(200, serde_json::to_value(attachments).unwrap()),
+        Err(_) => Respuesta::simple(400, "Bad request"),
+    }
+}
+
+#[get("/feedback/{id}/attachments/{attachment_id}")]
+pub async fn download_attachment(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path: web::Path<(i64, i64)>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let (id, attachment_id) = path.into_inner();
+    let attachment = match Attachment::read(&pool, id, attachment_id).await{
+        Ok(Some(attachment)) => attachment,
+        _ => return Respuesta::simple(404, &format!("Attachment {} not found", attachment_id)),
     };
+    match std::fs::read(&attachment.path){
+        Ok(content) => Ok(HttpResponse::Ok()
+            .content_type(attachment.mime_type.clone()
+                .unwrap_or_else(|| "application/octet-stream".to_string()))
+            .insert_header(ContentDisposition{
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::FilenameExt(ExtendedValue{
+                    charset: Charset::Ext("UTF-8".to_string()),
+                    language_tag: None,
+                    value: attachment.name().into_bytes(),
+                })],
+            })
+            .body(content)),
+        Err(e) => Respuesta::simple(500, &format!("Can not read attachment: {}", e)),
+    }
+}
 
-    match Feedback::new_from(&pool, &category, &reference, &content, &username, &nickname, applied, &source)
+#[derive(Deserialize)]
+pub struct PlaylistParams{
+    category: Option<String>,
+}
+
+#[get("/episodes/{episode}/playlist")]
+pub async fn read_playlist(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path: web::Path<String>, params: web::Query<PlaylistParams>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let episode = path.into_inner();
+    match Track::read_all(&pool, &episode, params.category.as_deref()).await{
+        Ok(tracks) => {
+            let items: Vec<Value> = tracks.iter().enumerate().map(|(position, track)| {
+                let mut item = serde_json::to_value(track).unwrap();
+                item["file"] = json!(track.file_name(position + 1));
+                item["url"] = json!(format!("/feedback/{}/attachments/{}",
+                                            track.feedback_id, track.attachment_id));
+                item
+            }).collect();
+            let duration: i64 = tracks.iter().filter_map(|track| track.duration).sum();
+            Respuesta::new(200, json!({
+                "episode": episode,
+                "duration": duration,
+                "tracks": items,
+            }))
+        },
+        Err(_) => Respuesta::simple(400, "Bad request"),
+    }
+}
+
+#[get("/episodes/{episode}/export")]
+pub async fn export_playlist(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        path: web::Path<String>, params: web::Query<PlaylistParams>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackRead)?;
+    let episode = path.into_inner();
+    let tracks = match Track::read_all(&pool, &episode, params.category.as_deref()).await{
+        Ok(tracks) => tracks,
+        Err(_) => return Respuesta::simple(400, "Bad request"),
+    };
+    match playlist::export(&tracks){
+        Ok(bundle) => Ok(HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header(("Content-Disposition",
+                            format!("attachment; filename=\"episodio-{}.zip\"", episode)))
+            .body(bundle)),
+        Err(e) => Respuesta::simple(500, &format!("Can not export playlist: {}", e)),
+    }
+}
+
+#[post("/feedback")]
+pub async fn create_feedback(api_key: ApiKey, pool: web::Data<SqlitePool>,
+        post: String) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::FeedbackWrite)?;
+    let data: FeedbackData = match serde_json::from_str(&post){
+        Ok(data) => data,
+        Err(e) => return Respuesta::simple(400, &format!("Bad request!, {}", e)),
+    };
+    match Feedback::new_from(&pool, &data, None)
         .await{
             Ok(feedback) => Respuesta::new(200, serde_json::to_value(feedback).unwrap()),
             Err(_) => Respuesta::simple(400, "Bad request"),
@@ -200,162 +335,181 @@ pub async fn create_feedback(req: HttpRequest, pool: web::Data<SqlitePool>,
 }
 
 #[get("/status")]
-pub async fn status(req: HttpRequest) -> Result<HttpResponse, Error>{
-    let token = format!("Bearer {}", env::var("TOKEN").expect("TOKEN not set"));
-    if !req.headers().contains_key(AUTHORIZATION) || 
-            req.headers().get(AUTHORIZATION).unwrap().to_str().unwrap() != token{
-        return Respuesta::simple(401, "Unauthorized");
+pub async fn status(api_key: ApiKey, telegram: web::Data<Telegram>,
+        webhook: web::Data<Webhook>) -> Result<HttpResponse, Error>{
+    api_key.require(Scope::Admin)?;
+    match telegram.get_webhook_info().await{
+        Ok(info) => {
+            let expected = webhook.url.clone().unwrap_or_default();
+            let mode = if webhook.url.is_some() {"webhook"} else {"polling"};
+            Respuesta::new(200, json!({
+                "description": "Up and running",
+                "mode": mode,
+                "webhook_ok": info.url == expected,
+                "webhook": info,
+            }))
+        },
+        Err(e) => {
+            println!("No he podido leer la información del webhook: {}", e);
+            Respuesta::simple(500, "Can not get webhook info")
+        },
     }
-    Respuesta::simple(200, "Up and running")
 }
 
 #[post("/hook")]
-pub async fn hook(req: HttpRequest, pool: web::Data<SqlitePool>, channels: web::Data<Channels>, post: String) -> Result<HttpResponse, Error>{
-    let mattermost_base_uri = env::var("MATTERMOST_BASE_URI").expect("Not found Mattermost Base Uri");
-    let mattermost_token = env::var("MATTERMOST_ACCESS_TOKEN").expect("Not found Mattermost token");
-    let mattermost = Mattermost::new(&mattermost_base_uri, &mattermost_token);
-    let zinc_base_url = env::var("ZINC_BASE_URL").expect("Not found zinc base url");
-    let zinc_indice = env::var("ZINC_INDICE").expect("Not found zinc indice");
-    let zinc_token = env::var("ZINC_TOKEN").expect("Not found token");
-    let zinc = Zinc::new(&zinc_base_url, &zinc_indice, &zinc_token);
-    zinc.publish(&json!([{
+pub async fn hook(req: HttpRequest, context: web::Data<BotContext>,
+        webhook: web::Data<Webhook>, post: String) -> Result<HttpResponse, Error>{
+    let authorized = match (&webhook.secret, req.headers().get(SECRET_TOKEN_HEADER)){
+        (Some(secret), Some(value)) => constant_time_eq(secret.as_bytes(), value.as_bytes()),
+        _ => false,
+    };
+    if !authorized{
+        return Respuesta::simple(401, "Unauthorized");
+    }
+    if let Err(e) = context.zinc.publish(&json!([{
         "src": "Telegram",
         "type": "webhook",
         "post": &post,
-    }])).await.unwrap();
-    let mut content: Value = serde_json::from_str(&post).unwrap();
-    if let Some(message) = content.get_mut("message"){
-        let (name, nick) = get_user(message);
-        let user = if !nick.is_empty() {format!("@{}", nick)} else {name.clone()};
-        let option_chat_id = get_chat_id(message);
-        let message_thread_id = get_message_thread_id(message);
-        if command("ayuda", message){
-            let text = "Ayuda:
-¿Como colaborar con tus ideas, preguntas y comentarios?
+    }])).await{
+        println!("No he podido publicar en zinc: {:?}", e);
+    }
+    let update: Update = match serde_json::from_str(&post){
+        Ok(update) => update,
+        Err(e) => return Respuesta::simple(400, &format!("Bad request!, invalid update: {}", e)),
+    };
+    process_update(&context, &update).await;
+    Ok(HttpResponse::Ok().finish())
+}
 
-Utilizando `hastags` (#),
+#[derive(Deserialize)]
+pub struct OutgoingWebhook{
+    token: String,
+    #[serde(default)]
+    user_id: String,
+    #[serde(default)]
+    user_name: String,
+    #[serde(default)]
+    post_id: String,
+    #[serde(default)]
+    text: String,
+}
 
-* Para sugerir una idea, utiliza `#idea`. Por ejemplo, `#idea esta  es una buena idea`
+#[post("/mattermost")]
+pub async fn mattermost_hook(pool: web::Data<SqlitePool>, hooks: web::Data<MattermostHooks>,
+        telegram: web::Data<Telegram>, mattermost: web::Data<Mattermost>,
+        payload: web::Either<web::Json<OutgoingWebhook>, web::Form<OutgoingWebhook>>)
+        -> Result<HttpResponse, Error>{
+    let payload = payload.into_inner();
+    if !hooks.webhook_tokens.iter().any(|token| constant_time_eq(token.as_bytes(), payload.token.as_bytes())){
+        return Respuesta::simple(401, "Unauthorized");
+    }
+    if hooks.user_id.as_deref() == Some(payload.user_id.as_str()) || payload.text.trim().is_empty(){
+        return Respuesta::simple(200, "Ignored");
+    }
+    if relay_reply(&pool, &telegram, &mattermost, &payload.post_id, &payload.user_name,
+                   &payload.text).await{
+        Respuesta::simple(200, "Relayed")
+    }else{
+        Respuesta::simple(200, "Ignored")
+    }
+}
 
-* En el caso de que quieras hacer una pregunta para los capítulos de preguntas y respuestas, utiliza `#pregunta`. Por ejemplo `¿Cuanto duermes? #pregunta`
+#[derive(Deserialize)]
+pub struct SlashCommand{
+    token: String,
+    #[serde(default)]
+    user_name: String,
+    #[serde(default)]
+    text: String,
+}
 
-* Si lo que quieres es hacer un comentario a un podcast utiliza `#comentario`. Por ejemplo `#comentario 123 me gusta`. Este comentario en concreto irá al podcast número 123
+#[post("/mattermost/command")]
+pub async fn mattermost_command(pool: web::Data<SqlitePool>, categories: web::Data<Categories>,
+        hooks: web::Data<MattermostHooks>, telegram: web::Data<Telegram>,
+        payload: web::Form<SlashCommand>) -> Result<HttpResponse, Error>{
+    let authorized = hooks.command_token.as_ref()
+        .map(|token| constant_time_eq(token.as_bytes(), payload.token.as_bytes()))
+        .unwrap_or(false);
+    if !authorized{
+        return Respuesta::simple(401, "Unauthorized");
+    }
+    let text = match Command::parse(&payload.text){
+        Ok(command) => slash::execute(&pool, &categories, &telegram, command,
+                                      &payload.user_name).await,
+        Err(e) => format!("{}\n\n{}", e, slash::USAGE),
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "response_type": "ephemeral",
+        "text": text,
+    })))
+}
 
-Indicarte que `#idea`, `#pregunta`, `#comentario` no tienen que ir necesariamenta al principio o al final del mensaje, pueden ir donde tu quieras.
-";
-            if let Some(chat_id) = option_chat_id{
-                send_message(chat_id, message_thread_id, text).await;
-            }
-        };
-        match check_key("idea", message){
-            Some(content) => {
-                if &content == ""{
-                    if let Some(chat_id) = option_chat_id{
-                        let text = format!("Tienes que escribir `/idea` seguido del contenido, {}", user);
-                        send_message(chat_id, message_thread_id, &text).await;
-                    }
-                }else{
-                    match Feedback::new_from(&pool, "idea", "", &content, &name, &nick, 0, "Telegram").await{
-                        Ok(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Muchas gracias por compartir tu idea {}", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                                mattermost.post_message(&channels.idea, &content, None).await.unwrap();
-                                zinc.publish(&json!([{
-                                    "src": "Telegram",
-                                    "type": "idea",
-                                    "from": &user,
-                                    "message": &content,
-                                }])).await.unwrap();
-                            }
-                        },
-                        Err(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Lo siento {}, no he podido registrar tu idea. Mira que está pasando @atareao!", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                            }
-
-                        },
-                    }
-                }
-            },
-            None => {},
-        }
-        match check_key("pregunta", message){
-            Some(content) => {
-                if &content == ""{
-                    if let Some(chat_id) = option_chat_id{
-                        let text = format!("Tienes que escribir `/pregunta` seguido del contenido, {}", user);
-                        send_message(chat_id, message_thread_id, &text).await;
-                    }
-                } else {
-                    match Feedback::new_from(&pool, "pregunta", "", &content, &name, &nick, 0, "Telegram").await{
-                        Ok(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Muchas gracias por tu pregunta {}", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                                mattermost.post_message(&channels.pregunta, &content, None).await.unwrap();
-                                zinc.publish(&json!([{
-                                    "src": "Telegram",
-                                    "type": "pregunta",
-                                    "from": &user,
-                                    "message": &content,
-                                }])).await.unwrap();
-                            }
-                        },
-                        Err(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Lo siento {}, no he podido registrar tu pregunta. Mira que está pasando @atareao!", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                            }
-
-                        },
-                    }
-                }
-            },
-            None => {},
-        }
-        match check_comment("comentario", message){
-            Some((refer, comment)) => {
-                let referencia = match refer{
-                    Some(refer) => refer,
-                    None => "".to_string()
-                };
-                let comentario = match comment{
-                    Some(comment) => comment,
-                    None => "".to_string()
-                };
-
-                if &comentario != ""{
-                    match Feedback::new_from(&pool, "comentario", &referencia, &comentario, &name, &nick, 0, "Telegram").await{
-                        Ok(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Muchas gracias por tu comentario {}", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                                mattermost.post_message(&channels.comentario, &comentario, None).await.unwrap();
-                                zinc.publish(&json!([{
-                                    "src": "Telegram",
-                                    "type": "pregunta",
-                                    "from": &user,
-                                    "message": &content,
-                                }])).await.unwrap();
-                            }
-                        },
-                        Err(_) => {
-                            if let Some(chat_id) = option_chat_id{
-                                let text = format!("Lo siento {}, no he podido registrar tu comentario. Mira que está pasando @atareao!", user);
-                                send_message(chat_id, message_thread_id, &text).await;
-                            }
-
-                        },
-                    }
-                }
+#[post("/mattermost/action")]
+pub async fn mattermost_action(pool: web::Data<SqlitePool>, bot_info: web::Data<BotInfo>,
+        telegram: web::Data<Telegram>, mattermost: web::Data<Mattermost>,
+        callback: web::Json<ActionCallback>) -> Result<HttpResponse, Error>{
+    let actions = match &bot_info.actions{
+        Some(actions) if actions.verify(&callback.context) => actions,
+        _ => return Respuesta::simple(401, "Unauthorized"),
+    };
+    let response = actions.handle(&pool, &telegram, &mattermost, &callback).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::routes::{hook, SECRET_TOKEN_HEADER};
+    use crate::{BotContext, BotInfo, Webhook};
+    use crate::config::Categories;
+    use crate::mattermost::Mattermost;
+    use crate::telegram::Telegram;
+    use crate::test_utils::memory_pool;
+    use crate::update::User;
+    use crate::zinc::Zinc;
+    use actix_web::{test, web, App, http::StatusCode};
+    use regex::Regex;
+
+    #[actix_rt::test]
+    async fn rejects_bad_updates() {
+        let context = BotContext{
+            pool: memory_pool().await,
+            categories: Categories::default(),
+            bot_info: BotInfo{
+                user: User{id: 1, is_bot: true, first_name: "bot".to_string(), last_name: None,
+                           username: Some("bot".to_string()), language_code: None},
+                mention_channel_id: None,
+                episode_pattern: Regex::new(r"\d+").unwrap(),
+                attachments_dir: std::env::temp_dir().to_string_lossy().to_string(),
+                actions: None,
+                upvote_emoji: Vec::new(),
             },
-            None => {},
-        }
-    }else{
-        println!("Desastre");
+            telegram: Telegram::new("token"),
+            mattermost: Mattermost::new("http://127.0.0.1:9", "token", "team"),
+            zinc: Zinc::new("127.0.0.1:9", "test", "token"),
+            draft_timeout: 600,
+        };
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(context))
+            .app_data(web::Data::new(Webhook{url: None, secret: Some("secret".to_string())}))
+            .service(hook)).await;
+
+        let request = test::TestRequest::post().uri("/hook")
+            .set_payload(r#"{"update_id": 1}"#)
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::UNAUTHORIZED);
+
+        let request = test::TestRequest::post().uri("/hook")
+            .insert_header((SECRET_TOKEN_HEADER, "secret"))
+            .set_payload(r#"{"message": "hola"}"#)
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+
+        let request = test::TestRequest::post().uri("/hook")
+            .insert_header((SECRET_TOKEN_HEADER, "secret"))
+            .set_payload(r#"{"update_id": 1}"#)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(test::read_body(response).await.is_empty());
     }
-    Respuesta::new(200, json!({"content": post}))
 }
-
@@ -0,0 +1,225 @@
+use actix_web::web;
+use sqlx::sqlite::SqlitePool;
+use std::str::FromStr;
+
+use crate::{
+    feedback::{Feedback, FeedbackQuery, Status, SortField, Direction},
+    bot::notify_author,
+    telegram::Telegram,
+    config::{Categories, MENTION},
+};
+
+const LIMIT: i64 = 20;
+const WIDTH: usize = 60;
+
+pub const USAGE: &str = "Uso:
+* `/feedback list [categoría] [estado]` lista el feedback más reciente
+* `/feedback show <id>` muestra un feedback
+* `/feedback status <id> <estado>` cambia el estado
+* `/feedback search <términos>` busca en el contenido
+
+Estados: new, triaged, planned, answered, rejected, duplicate, done";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command{
+    List{category: Option<String>, status: Option<Status>},
+    Show(i64),
+    Status(i64, Status),
+    Search(String),
+    Help,
+}
+
+fn parse_id(word: Option<&str>) -> Result<i64, String>{
+    match word{
+        Some(word) => word.trim_start_matches('#')
+            .parse()
+            .map_err(|_| format!("`{}` no es un identificador", word)),
+        None => Err("Falta el identificador del feedback".to_string()),
+    }
+}
+
+impl Command{
+    pub fn parse(text: &str) -> Result<Command, String>{
+        let mut words = text.split_whitespace();
+        match words.next(){
+            None | Some("help") => Ok(Command::Help),
+            Some("list") => {
+                let mut category = None;
+                let mut status = None;
+                for word in words{
+                    match Status::from_str(word){
+                        Ok(value) => status = Some(value),
+                        Err(_) => category = Some(word.to_string()),
+                    }
+                }
+                Ok(Command::List{category, status})
+            },
+            Some("show") => parse_id(words.next()).map(Command::Show),
+            Some("status") => {
+                let id = parse_id(words.next())?;
+                match words.next(){
+                    Some(word) => Status::from_str(word)
+                        .map(|status| Command::Status(id, status))
+                        .map_err(|_| format!("`{}` no es un estado", word)),
+                    None => Err("Falta el nuevo estado".to_string()),
+                }
+            },
+            Some("search") => {
+                let terms = words.collect::<Vec<&str>>().join(" ");
+                if terms.is_empty(){
+                    Err("Falta lo que quieres buscar".to_string())
+                }else{
+                    Ok(Command::Search(terms))
+                }
+            },
+            Some(other) => Err(format!("No conozco el subcomando `{}`", other)),
+        }
+    }
+}
+
+fn cell(text: &str) -> String{
+    let text = text.replace('|', "\\|").replace('\n', " ");
+    if text.chars().count() > WIDTH{
+        format!("{}…", text.chars().take(WIDTH).collect::<String>())
+    }else{
+        text
+    }
+}
+
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String{
+    let mut lines = vec![
+        format!("| {} |", headers.join(" | ")),
+        format!("|{}", "---|".repeat(headers.len())),
+    ];
+    for row in rows{
+        let cells: Vec<String> = row.iter().map(|value| cell(value)).collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+    }
+    lines.join("\n")
+}
+
+fn author(feedback: &Feedback) -> String{
+    if feedback.nickname.is_empty() {feedback.username.clone()} else {format!("@{}", feedback.nickname)}
+}
+
+fn feedback_table(items: &[Feedback]) -> String{
+    let rows: Vec<Vec<String>> = items.iter().map(|feedback| vec![
+        feedback.id.to_string(),
+        feedback.category.clone(),
+        feedback.status.to_string(),
+        author(feedback),
+        feedback.content.clone(),
+        feedback.created_at.format("%Y-%m-%d").to_string(),
+    ]).collect();
+    table(&["Id", "Categoría", "Estado", "Autor", "Contenido", "Fecha"], &rows)
+}
+
+pub async fn execute(pool: &web::Data<SqlitePool>, categories: &Categories, telegram: &Telegram,
+        command: Command, actor: &str) -> String{
+    match command{
+        Command::Help => USAGE.to_string(),
+        Command::List{category, status} => {
+            if let Some(name) = &category{
+                if name != MENTION && categories.get(name).is_none(){
+                    let names: Vec<String> = categories.iter()
+                        .map(|category| format!("`{}`", category.name))
+                        .collect();
+                    return format!("No existe la categoría `{}`. Las categorías son {}",
+                                   name, names.join(", "));
+                }
+            }
+            let query = FeedbackQuery{
+                category,
+                status,
+                sort: Some(SortField::CreatedAt),
+                direction: Some(Direction::Desc),
+                limit: Some(LIMIT),
+                ..Default::default()
+            };
+            match query.fetch(pool, None).await{
+                Ok(page) if page.items.is_empty() => "No hay feedback".to_string(),
+                Ok(page) => feedback_table(&page.items),
+                Err(e) => format!("No he podido leer el feedback: {}", e),
+            }
+        },
+        Command::Show(id) => {
+            let feedback = match Feedback::read(pool, id).await{
+                Ok(feedback) => feedback,
+                Err(_) => return format!("No existe el feedback {}", id),
+            };
+            let rows = vec![
+                vec!["Categoría".to_string(), feedback.category.clone()],
+                vec!["Referencia".to_string(), feedback.reference.clone()],
+                vec!["Estado".to_string(), feedback.status.to_string()],
+                vec!["Autor".to_string(), author(&feedback)],
+                vec!["Origen".to_string(), feedback.source.clone()],
+                vec!["Creado".to_string(), feedback.created_at.format("%Y-%m-%d %H:%M").to_string()],
+                vec!["Actualizado".to_string(), feedback.updated_at.format("%Y-%m-%d %H:%M").to_string()],
+            ];
+            format!("#### Feedback {}\n{}\n\n> {}", feedback.id,
+                    table(&["Campo", "Valor"], &rows), feedback.content.replace('\n', "\n> "))
+        },
+        Command::Status(id, to) => {
+            let feedback = match Feedback::read(pool, id).await{
+                Ok(feedback) => feedback,
+                Err(_) => return format!("No existe el feedback {}", id),
+            };
+            match feedback.transition(pool, to, actor).await{
+                Ok(feedback) => {
+                    if let Err(e) = notify_author(telegram, &feedback).await{
+                        println!("No he podido avisar al autor de {}: {}", feedback.id, e);
+                    }
+                    format!("El feedback {} ahora está en `{}`\n{}", feedback.id, feedback.status,
+                            feedback_table(std::slice::from_ref(&feedback)))
+                },
+                Err(e) => format!("No he podido cambiar el estado de {}: {}", id, e),
+            }
+        },
+        Command::Search(terms) => match Feedback::search(pool, &terms, LIMIT).await{
+            Ok(results) if results.is_empty() => format!("No hay resultados para `{}`", terms),
+            Ok(results) => {
+                let rows: Vec<Vec<String>> = results.iter().map(|result| vec![
+                    result.feedback.id.to_string(),
+                    result.feedback.category.clone(),
+                    result.feedback.status.to_string(),
+                    author(&result.feedback),
+                    result.snippet.clone(),
+                ]).collect();
+                table(&["Id", "Categoría", "Estado", "Autor", "Fragmento"], &rows)
+            },
+            Err(e) => format!("No he podido buscar `{}`: {}", terms, e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::feedback::Status;
+    use crate::slash::{Command, table};
+
+    #[test]
+    fn parses_subcommands() {
+        assert_eq!(Command::parse("").unwrap(), Command::Help);
+        assert_eq!(Command::parse("list idea").unwrap(),
+                   Command::List{category: Some("idea".to_string()), status: None});
+        assert_eq!(Command::parse("list answered").unwrap(),
+                   Command::List{category: None, status: Some(Status::Answered)});
+        assert_eq!(Command::parse("show #42").unwrap(), Command::Show(42));
+        assert_eq!(Command::parse("status 42 answered").unwrap(),
+                   Command::Status(42, Status::Answered));
+        assert_eq!(Command::parse("search  rust   async").unwrap(),
+                   Command::Search("rust async".to_string()));
+        assert!(Command::parse("status 42 solved").is_err());
+        assert!(Command::parse("show cuarenta").is_err());
+        assert!(Command::parse("search").is_err());
+        assert!(Command::parse("borrar 42").is_err());
+    }
+
+    #[test]
+    fn escapes_table_cells() {
+        let rows = vec![vec!["1".to_string(), "a | b\nc".to_string()]];
+        assert_eq!(table(&["Id", "Texto"], &rows), "| Id | Texto |\n|---|---|\n| 1 | a \\| b c |");
+        let long = vec![vec!["x".repeat(70)]];
+        assert!(table(&["Texto"], &long).ends_with(&format!("{}… |", "x".repeat(60))));
+    }
+}
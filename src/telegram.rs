@@ -1,42 +1,221 @@
 use reqwest::Client;
-use serde::{Serialize, Deserialize};
-use std::env;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Message{
+use crate::update::{Message, User, File, InlineKeyboardMarkup};
+
+#[derive(Debug)]
+pub enum TelegramError{
+    ReqwestError(reqwest::Error),
+    ApiError{
+        code: i64,
+        description: String,
+    },
+}
+
+impl fmt::Display for TelegramError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            TelegramError::ReqwestError(e) => write!(f, "Request error: {}", e),
+            TelegramError::ApiError{code, description} =>
+                write!(f, "Telegram error {}: {}", code, description),
+        }
+    }
+}
+
+impl From<reqwest::Error> for TelegramError{
+    fn from(e: reqwest::Error) -> Self{
+        TelegramError::ReqwestError(e.without_url())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Respuesta<T>{
+    ok: bool,
+    result: Option<T>,
+    error_code: Option<i64>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum ParseMode{
+    Markdown,
+    #[serde(rename = "HTML")]
+    Html,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SendMessage{
     chat_id: i64,
-    message_thread_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_thread_id: Option<i64>,
     text: String,
-
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to_message_id: Option<i64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    allow_sending_without_reply: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_markup: Option<InlineKeyboardMarkup>,
 }
 
-impl Message {
-    fn new(chat_id: i64, message_thread_id: i64, text: &str) -> Self{
-        Message{
+impl SendMessage{
+    pub fn new(chat_id: i64, text: &str) -> Self{
+        Self{
             chat_id,
-            message_thread_id,
+            message_thread_id: None,
             text: text.to_string(),
+            parse_mode: None,
+            reply_to_message_id: None,
+            allow_sending_without_reply: false,
+            disable_notification: false,
+            reply_markup: None,
         }
     }
+
+    pub fn thread(mut self, message_thread_id: Option<i64>) -> Self{
+        self.message_thread_id = message_thread_id;
+        self
+    }
+
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self{
+        self.parse_mode = Some(parse_mode);
+        self
+    }
+
+    pub fn reply_to(mut self, message_id: i64) -> Self{
+        self.reply_to_message_id = Some(message_id);
+        self.allow_sending_without_reply = true;
+        self
+    }
+
+    pub fn silent(mut self) -> Self{
+        self.disable_notification = true;
+        self
+    }
+
+    pub fn keyboard(mut self, reply_markup: InlineKeyboardMarkup) -> Self{
+        self.reply_markup = Some(reply_markup);
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookInfo{
+    pub url: String,
+    pub has_custom_certificate: bool,
+    pub pending_update_count: i64,
+    pub ip_address: Option<String>,
+    pub last_error_date: Option<i64>,
+    pub last_error_message: Option<String>,
+    pub max_connections: Option<i64>,
+    pub allowed_updates: Option<Vec<String>>,
 }
 
-pub async fn send_message(chat_id: i64, message_thread_id: Option<i64>, text: &str) -> Option<String>{
-    let token = env::var("TG_TOKEN").expect("TG_TOKEN not set");
-    let url = format!("https://api.telegram.org/bot{}/sendMessage", token);
-    let message = Message::new(chat_id, message_thread_id.unwrap(), text);
-    println!("{}", serde_json::to_string(&message).unwrap());
-    match Client::new()
-        .post(url)
-        .json(&message)
-        .send()
-        .await{
-            Ok(response) => {
-                println!("Mensaje envíado: {}", response.status().to_string());
-                Some(response.status().to_string())
-            },
-            Err(error) => {
-                println!("No he podido enviar el mensaje: {}",error.to_string());
-                None
-            },
+const ALLOWED_UPDATES: [&str; 6] = ["message", "edited_message", "channel_post",
+    "edited_channel_post", "callback_query", "message_reaction"];
+
+#[derive(Debug, Clone)]
+pub struct Telegram{
+    base_uri: String,
+    file_uri: String,
+    client: Client,
+}
+
+impl Telegram{
+    pub fn new(token: &str) -> Self{
+        Self{
+            base_uri: format!("https://api.telegram.org/bot{}", token),
+            file_uri: format!("https://api.telegram.org/file/bot{}", token),
+            client: Client::new(),
+        }
+    }
+
+    pub async fn send_message(&self, message: &SendMessage) -> Result<Message, TelegramError>{
+        self.call("sendMessage", &serde_json::to_value(message).unwrap(), None).await
+    }
+
+    pub async fn edit_message_text(&self, chat_id: i64, message_id: i64, text: &str,
+            reply_markup: Option<&InlineKeyboardMarkup>) -> Result<Value, TelegramError>{
+        let mut body = json!({
+            "chat_id": chat_id,
+            "message_id": message_id,
+            "text": text,
+        });
+        if let Some(markup) = reply_markup{
+            body["reply_markup"] = serde_json::to_value(markup).unwrap();
         }
+        self.call("editMessageText", &body, None).await
+    }
+
+    pub async fn answer_callback_query(&self, callback_query_id: &str,
+            text: Option<&str>) -> Result<bool, TelegramError>{
+        let body = json!({
+            "callback_query_id": callback_query_id,
+            "text": text,
+        });
+        self.call("answerCallbackQuery", &body, None).await
+    }
+
+    pub async fn get_updates(&self, offset: Option<i64>, timeout: u64) -> Result<Vec<Value>, TelegramError>{
+        let body = json!({
+            "offset": offset,
+            "timeout": timeout,
+            "allowed_updates": ALLOWED_UPDATES,
+        });
+        self.call("getUpdates", &body, Some(Duration::from_secs(timeout + 10))).await
+    }
+
+    pub async fn set_webhook(&self, url: &str, secret_token: &str) -> Result<bool, TelegramError>{
+        let body = json!({
+            "url": url,
+            "secret_token": secret_token,
+            "allowed_updates": ALLOWED_UPDATES,
+        });
+        self.call("setWebhook", &body, None).await
+    }
+
+    pub async fn delete_webhook(&self) -> Result<bool, TelegramError>{
+        self.call("deleteWebhook", &json!({}), None).await
+    }
+
+    pub async fn get_file(&self, file_id: &str) -> Result<File, TelegramError>{
+        self.call("getFile", &json!({"file_id": file_id}), None).await
+    }
+
+    pub async fn download_file(&self, file_path: &str) -> Result<Vec<u8>, TelegramError>{
+        let url = format!("{}/{}", self.file_uri, file_path);
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    pub async fn get_me(&self) -> Result<User, TelegramError>{
+        self.call("getMe", &json!({}), None).await
+    }
+
+    pub async fn get_webhook_info(&self) -> Result<WebhookInfo, TelegramError>{
+        self.call("getWebhookInfo", &json!({}), None).await
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, body: &Value,
+            timeout: Option<Duration>) -> Result<T, TelegramError>{
+        let url = format!("{}/{}", self.base_uri, method);
+        let mut request = self.client.post(url).json(body);
+        if let Some(timeout) = timeout{
+            request = request.timeout(timeout);
+        }
+        let respuesta: Respuesta<T> = request.send().await?.json().await?;
+        match (respuesta.ok, respuesta.result){
+            (true, Some(result)) => Ok(result),
+            _ => Err(TelegramError::ApiError{
+                code: respuesta.error_code.unwrap_or_default(),
+                description: respuesta.description.unwrap_or_else(|| "Unknown error".to_string()),
+            }),
+        }
+    }
 }
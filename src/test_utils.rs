@@ -0,0 +1,12 @@
+use actix_web::web;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+
+pub async fn memory_pool() -> web::Data<SqlitePool>{
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+    web::Data::new(pool)
+}
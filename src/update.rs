@@ -0,0 +1,202 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Update{
+    pub update_id: i64,
+    pub message: Option<Message>,
+    pub edited_message: Option<Message>,
+    pub channel_post: Option<Message>,
+    pub edited_channel_post: Option<Message>,
+    pub callback_query: Option<CallbackQuery>,
+    pub message_reaction: Option<MessageReactionUpdated>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message{
+    pub message_id: i64,
+    pub message_thread_id: Option<i64>,
+    pub from: Option<User>,
+    pub sender_chat: Option<Chat>,
+    pub date: i64,
+    pub chat: Chat,
+    pub edit_date: Option<i64>,
+    #[serde(default)]
+    pub is_topic_message: bool,
+    #[serde(default)]
+    pub is_automatic_forward: bool,
+    pub forward_origin: Option<MessageOrigin>,
+    pub reply_to_message: Option<Box<Message>>,
+    pub text: Option<String>,
+    #[serde(default)]
+    pub entities: Vec<MessageEntity>,
+    pub caption: Option<String>,
+    #[serde(default)]
+    pub caption_entities: Vec<MessageEntity>,
+    #[serde(default)]
+    pub photo: Vec<PhotoSize>,
+    pub voice: Option<Media>,
+    pub audio: Option<Media>,
+    pub video: Option<Media>,
+    pub document: Option<Media>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhotoSize{
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub width: i64,
+    pub height: i64,
+    pub file_size: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Media{
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub duration: Option<i64>,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct File{
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub file_size: Option<i64>,
+    pub file_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User{
+    pub id: i64,
+    pub is_bot: bool,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub language_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatType{
+    Private,
+    Group,
+    Supergroup,
+    Channel,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chat{
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: ChatType,
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    #[serde(default)]
+    pub is_forum: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageOrigin{
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub date: i64,
+    pub chat: Option<Chat>,
+    pub message_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityType{
+    Mention,
+    Hashtag,
+    Cashtag,
+    BotCommand,
+    Url,
+    Email,
+    PhoneNumber,
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    Code,
+    Pre,
+    TextLink,
+    TextMention,
+    CustomEmoji,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEntity{
+    #[serde(rename = "type")]
+    pub kind: EntityType,
+    pub offset: i64,
+    pub length: i64,
+    pub url: Option<String>,
+    pub user: Option<User>,
+    pub language: Option<String>,
+    pub custom_emoji_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionType{
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub emoji: Option<String>,
+    pub custom_emoji_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageReactionUpdated{
+    pub chat: Chat,
+    pub message_id: i64,
+    pub user: Option<User>,
+    pub actor_chat: Option<Chat>,
+    pub date: i64,
+    #[serde(default)]
+    pub old_reaction: Vec<ReactionType>,
+    #[serde(default)]
+    pub new_reaction: Vec<ReactionType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallbackQuery{
+    pub id: String,
+    pub from: User,
+    pub message: Option<Message>,
+    pub inline_message_id: Option<String>,
+    pub chat_instance: String,
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InlineKeyboardMarkup{
+    pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InlineKeyboardButton{
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl InlineKeyboardButton{
+    pub fn callback(text: &str, data: &str) -> Self{
+        Self{
+            text: text.to_string(),
+            callback_data: Some(data.to_string()),
+            url: None,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use actix_web::web;
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, query, Row, Error};
+use chrono::Utc;
+
+pub const TELEGRAM: &str = "telegram";
+pub const MATTERMOST: &str = "mattermost";
+
+pub struct Vote;
+
+impl Vote{
+    pub async fn cast(pool: &web::Data<SqlitePool>, feedback_id: i64, source: &str,
+            user_id: &str) -> Result<bool, Error>{
+        let result = query("INSERT OR IGNORE INTO votes (feedback_id, source, user_id,
+                            created_at) VALUES ($1, $2, $3, $4)")
+            .bind(feedback_id)
+            .bind(source)
+            .bind(user_id)
+            .bind(Utc::now().naive_utc())
+            .execute(pool.get_ref())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn withdraw(pool: &web::Data<SqlitePool>, feedback_id: i64, source: &str,
+            user_id: &str) -> Result<bool, Error>{
+        let result = query("DELETE FROM votes WHERE feedback_id = $1 AND source = $2
+                            AND user_id = $3")
+            .bind(feedback_id)
+            .bind(source)
+            .bind(user_id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn sync(pool: &web::Data<SqlitePool>, feedback_id: i64, source: &str,
+            user_ids: &[String]) -> Result<(), Error>{
+        let mut tx = pool.begin().await?;
+        let current: Vec<String> = query("SELECT user_id FROM votes WHERE feedback_id = $1
+                                          AND source = $2")
+            .bind(feedback_id)
+            .bind(source)
+            .map(|row: SqliteRow| row.get("user_id"))
+            .fetch_all(&mut tx)
+            .await?;
+        for user_id in current.iter().filter(|user_id| !user_ids.contains(user_id)){
+            query("DELETE FROM votes WHERE feedback_id = $1 AND source = $2 AND user_id = $3")
+                .bind(feedback_id)
+                .bind(source)
+                .bind(user_id)
+                .execute(&mut tx)
+                .await?;
+        }
+        let created_at = Utc::now().naive_utc();
+        for user_id in user_ids.iter().filter(|user_id| !current.contains(user_id)){
+            query("INSERT OR IGNORE INTO votes (feedback_id, source, user_id, created_at)
+                   VALUES ($1, $2, $3, $4)")
+                .bind(feedback_id)
+                .bind(source)
+                .bind(user_id)
+                .bind(created_at)
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::feedback::{Feedback, FeedbackData, FeedbackQuery, SortField, Direction, Cursor, Status};
+    use crate::vote::{Vote, TELEGRAM, MATTERMOST};
+    use actix_web::web;
+    use crate::test_utils::memory_pool;
+    use sqlx::{sqlite::SqlitePool, Row};
+
+    async fn count(pool: &web::Data<SqlitePool>, feedback_id: i64) -> i64{
+        sqlx::query("SELECT COUNT(*) AS votes FROM votes WHERE feedback_id = $1")
+            .bind(feedback_id)
+            .fetch_one(pool.get_ref())
+            .await
+            .unwrap()
+            .get("votes")
+    }
+
+    #[actix_rt::test]
+    async fn counts_and_sorts_votes() {
+        let pool = memory_pool().await;
+        let mut ids = Vec::new();
+        for content in ["uno", "dos", "tres"]{
+            let feedback = Feedback::new_from(&pool, &FeedbackData::new("idea", "", content,
+                "Lorenzo", "atareao", "Telegram"), None).await.unwrap();
+            ids.push(feedback.id);
+        }
+
+        assert!(Vote::cast(&pool, ids[1], TELEGRAM, "1").await.unwrap());
+        assert!(!Vote::cast(&pool, ids[1], TELEGRAM, "1").await.unwrap());
+        Vote::cast(&pool, ids[1], TELEGRAM, "2").await.unwrap();
+        Vote::sync(&pool, ids[2], MATTERMOST, &["a".to_string()]).await.unwrap();
+        Vote::sync(&pool, ids[1], MATTERMOST, &["a".to_string(), "b".to_string()]).await.unwrap();
+        Vote::sync(&pool, ids[1], MATTERMOST, &["b".to_string()]).await.unwrap();
+        assert_eq!(count(&pool, ids[1]).await, 3);
+        assert!(Vote::withdraw(&pool, ids[1], TELEGRAM, "2").await.unwrap());
+        assert_eq!(count(&pool, ids[1]).await, 2);
+
+        let query = FeedbackQuery{
+            sort: Some(SortField::Votes),
+            direction: Some(Direction::Desc),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = query.fetch(&pool, None).await.unwrap();
+        let votes: Vec<(i64, Option<i64>)> = page.items.iter()
+            .map(|item| (item.id, item.votes))
+            .collect();
+        assert_eq!(votes, [(ids[1], Some(2)), (ids[2], Some(1))]);
+        let cursor = Cursor::decode(&page.next.unwrap()).unwrap();
+        let page = query.fetch(&pool, Some(&cursor)).await.unwrap();
+        assert_eq!(page.items[0].id, ids[0]);
+
+        let top = Feedback::top(&pool, &["idea", "pregunta"], 10).await.unwrap();
+        assert_eq!(top.iter().map(|item| item.id).collect::<Vec<i64>>(), [ids[1], ids[2]]);
+        assert!(Feedback::top(&pool, &["pregunta"], 10).await.unwrap().is_empty());
+        Feedback::read(&pool, ids[2]).await.unwrap()
+            .transition(&pool, Status::Rejected, "test").await.unwrap();
+        assert_eq!(Feedback::top(&pool, &["idea"], 10).await.unwrap().len(), 1);
+    }
+}